@@ -1,15 +1,308 @@
 use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use rand::{distributions::Alphanumeric, Rng};
-use serde::{Deserialize, Serialize};
+use rand::{seq::SliceRandom, Rng};
+use serde::{
+    de::{self, IntoDeserializer, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
 use uuid::Uuid;
 
+use crate::nicknames;
+
+pub mod snapshot;
+
+/// Drawing player is considered idle when no segments arrive for this long
+const DRAWER_IDLE_AFTER: Duration = Duration::from_secs(3);
+
+/// Drawing player can mark a guess as close at most once per this interval
+const MARK_CLOSE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Guessing player stops typing unless they tell us otherwise within this duration
+const TYPING_EXPIRES_AFTER: Duration = Duration::from_secs(5);
+
+/// Wrong guess is considered close when it is within this edit distance from the word
+const CLOSE_GUESS_DISTANCE: usize = 2;
+
+/// Allowed canvas dimensions in pixels
+const MIN_CANVAS_SIZE: u32 = 16;
+const MAX_CANVAS_SIZE: u32 = 4096;
+
+/// Max length of a word (or a phrase) to draw
+const MAX_WORD_LEN: usize = 40;
+
+/// How many last roster changes to keep. Clients further behind get a full roster.
+const MAX_ROSTER_CHANGES: usize = 100;
+
+/// How many client identities to remember nicknames for
+const MAX_IDENTITIES: usize = 10_000;
+
+/// How many last player reports to keep per game
+const MAX_REPORTS: usize = 100;
+
+/// Max length of a report reason
+const MAX_REPORT_REASON_LEN: usize = 200;
+
+/// Server wide configuration of the games
+#[derive(Debug, Clone)]
+pub struct GamesConfig {
+    /// How many last turns to keep in game history
+    pub history_limit: usize,
+    /// Merge consecutive compatible line segments that arrive within this duration.
+    /// Disabled when None.
+    pub merge_segments_within: Option<Duration>,
+    /// How to generate game ids
+    pub room_codes: RoomCodeConfig,
+    /// Removed players who reconnect within this duration get their identity back
+    pub restore_removed_within: Duration,
+    /// Remove unfinished segment when drawing player disconnects.
+    /// Segments are finished with an explicit end of segment event.
+    pub drop_uncommitted_segments: bool,
+    /// Simplify oldest strokes once a drawing has more points than this.
+    /// Unlimited when None.
+    pub max_drawing_points: Option<usize>,
+    /// End the round when no guessing players are connected for this long.
+    /// Disabled when None.
+    pub end_round_without_guessers_after: Option<Duration>,
+    /// How many last incoming events to keep per game for bug reports.
+    /// Disabled when None.
+    pub audit_limit: Option<usize>,
+    /// Lowercase words or phrases that players cannot choose to draw
+    pub blocked_words: Vec<String>,
+    /// Notify the host once this many players have reported the same player
+    pub report_threshold: usize,
+}
+
+impl Default for GamesConfig {
+    fn default() -> Self {
+        Self {
+            history_limit: 50,
+            merge_segments_within: None,
+            room_codes: RoomCodeConfig::default(),
+            restore_removed_within: Duration::from_secs(10 * 60),
+            end_round_without_guessers_after: None,
+            max_drawing_points: None,
+            drop_uncommitted_segments: false,
+            audit_limit: None,
+            blocked_words: vec![],
+            report_threshold: 3,
+        }
+    }
+}
+
+impl GamesConfig {
+    /// Optional features that are enabled by this configuration
+    pub fn features(&self) -> Vec<&'static str> {
+        let mut features = vec![];
+        if self.merge_segments_within.is_some() {
+            features.push("mergeSegments");
+        }
+        if self.restore_removed_within > Duration::from_secs(0) {
+            features.push("restoreRemovedPlayers");
+        }
+        features
+    }
+}
+
+/// Configuration of generated game ids
+#[derive(Debug, Clone)]
+pub struct RoomCodeConfig {
+    /// Characters to pick from. Must not be empty.
+    pub alphabet: Vec<char>,
+    /// Length of generated ids. Grows when an id collides with an existing one.
+    pub min_len: usize,
+}
+
+impl Default for RoomCodeConfig {
+    fn default() -> Self {
+        Self {
+            alphabet: ('0'..='9').chain('A'..='Z').chain('a'..='z').collect(),
+            min_len: 6,
+        }
+    }
+}
+
+impl RoomCodeConfig {
+    /// Generate a random code of given length
+    fn generate(&self, len: usize) -> String {
+        let mut rng = rand::thread_rng();
+        (0..len)
+            .map(|_| *self.alphabet.choose(&mut rng).expect("Non empty alphabet"))
+            .collect()
+    }
+}
+
+/// Settings chosen by the host when creating a game
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct GameSettings {
+    pub scoring: ScoringPolicy,
+    /// How many guesses every player can make per round. Unlimited when None.
+    pub max_guesses: Option<u32>,
+    /// Whether drawing player sees how many guessers are close to the word
+    pub guess_progress: bool,
+    /// What happens when drawing player disconnects mid-round
+    pub drawer_disconnect: DrawerDisconnect,
+    /// Require guesses to match the word exactly, including letter case
+    pub strict_match: bool,
+    /// How many players can join the game. Unlimited when None.
+    pub max_players: Option<usize>,
+    /// How long to show the round summary before the next player chooses a word.
+    /// Next round starts right away when None.
+    pub round_summary_secs: Option<u64>,
+    /// Colors drawing player can use, e.g. "rgb(0, 0, 0)" or "#000000".
+    /// Strokes are snapped to the nearest allowed color. Unrestricted when empty.
+    #[serde(deserialize_with = "comma_separated")]
+    pub palette: Vec<String>,
+    /// Forfeit the round when drawing player draws nothing for this long.
+    /// Disabled when None.
+    pub forfeit_idle_drawer_secs: Option<u64>,
+    /// Points taken from drawing player who forfeits the round for not drawing
+    pub idle_drawer_penalty: u32,
+    /// Don't reveal any letters of the word to guessing players
+    pub hints_disabled: bool,
+    /// Drawing tools players can use. All tools are allowed when empty.
+    #[serde(deserialize_with = "comma_separated")]
+    pub allowed_tools: Vec<Tool>,
+    /// Abort the round when fewer guessing players are connected.
+    /// Round continues while at least one guessing player is connected when None.
+    pub min_guessers: Option<usize>,
+}
+
+/// Deserialize a list from a sequence or from a comma separated string, e.g. in a query string.
+/// Commas inside parentheses don't split items so that "rgb(0, 0, 0),#ffffff" is two colors.
+fn comma_separated<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    struct ListVisitor<T>(std::marker::PhantomData<T>);
+
+    impl<'de, T: Deserialize<'de>> Visitor<'de> for ListVisitor<T> {
+        type Value = Vec<T>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a list or a comma separated string")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<Vec<T>, E> {
+            let mut items = vec![];
+            let mut depth = 0;
+            let mut start = 0;
+            for (i, c) in value.char_indices() {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    ',' if depth == 0 => {
+                        items.push(&value[start..i]);
+                        start = i + 1;
+                    }
+                    _ => {}
+                }
+            }
+            items.push(&value[start..]);
+            items
+                .into_iter()
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| T::deserialize(item.into_deserializer()))
+                .collect()
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<T>, A::Error> {
+            let mut items = vec![];
+            while let Some(item) = seq.next_element()? {
+                items.push(item);
+            }
+            Ok(items)
+        }
+    }
+
+    deserializer.deserialize_any(ListVisitor(std::marker::PhantomData))
+}
+
+/// Drawing tool that produced a segment
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Tool {
+    /// Freehand line
+    Pen,
+    /// Emoji or shape placed at a point
+    Stamp,
+}
+
+/// How to handle drawing player disconnecting mid-round
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DrawerDisconnect {
+    /// Keep the drawing and wait for the player to come back
+    #[default]
+    Wait,
+    /// End the round right away, reveal the word and pass the turn to the next player
+    Forfeit,
+}
+
+/// How points are awarded at the end of a round
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ScoringPolicy {
+    /// Guessing player gets a point
+    #[default]
+    Flat,
+    /// The faster the word is guessed the more points guessing player gets.
+    /// Drawing player gets half of that.
+    TimeBased,
+}
+
+/// What happened during the round
+struct Round {
+    drawing_player_id: Uuid,
+    guessing_player_id: Uuid,
+    time_taken: Duration,
+}
+
+impl ScoringPolicy {
+    /// Max points for a time based round
+    const MAX_POINTS: u32 = 10;
+    /// Time based award decreases by a point after every period
+    const POINT_PERIOD: Duration = Duration::from_secs(15);
+
+    /// Compute points for the players in the round
+    fn award(&self, round: &Round) -> HashMap<Uuid, u32> {
+        let mut points = HashMap::new();
+        match self {
+            ScoringPolicy::Flat => {
+                points.insert(round.guessing_player_id, 1);
+            }
+            ScoringPolicy::TimeBased => {
+                let periods = round.time_taken.as_secs() / Self::POINT_PERIOD.as_secs();
+                let guesser_points = Self::MAX_POINTS.saturating_sub(periods as u32).max(1);
+                points.insert(round.guessing_player_id, guesser_points);
+                points.insert(round.drawing_player_id, guesser_points / 2);
+            }
+        }
+        points
+    }
+}
+
 #[derive(Debug)]
 pub struct Games {
-    /// Reserved game ids
-    pending_ids: HashSet<String>,
+    /// Reserved game ids with settings to create the game with
+    pending_ids: HashMap<String, GameSettings>,
     /// Games with joined players
     rooms: HashMap<String, Game>,
+    /// Recently removed players by game id and player id together with the removal time
+    removed_players: HashMap<(String, Uuid), (Player, Instant)>,
+    /// Last nickname by client identity. Lets the same browser keep its nickname across games.
+    identities: HashMap<Uuid, String>,
+    /// Last incoming events by game id if enabled in the config.
+    /// Kept outside of the game so that cloning a game doesn't copy them.
+    audits: HashMap<String, Vec<AuditEntry>>,
+    /// Last reports of abusive players by game id
+    reports: HashMap<String, Vec<Report>>,
+    /// Shared with every game
+    config: Arc<GamesConfig>,
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -17,67 +310,299 @@ pub struct Game {
     pub id: String,
     stage: GameStage,
     pub players: Vec<Player>,
+    /// Last turns of the game. Oldest turns are evicted once limit is reached.
     history: Vec<Turn>,
+    #[serde(skip)]
+    config: Arc<GamesConfig>,
+    /// Total points per player. Aggregated separately so that evicting history doesn't lose points.
+    scores: HashMap<Uuid, u32>,
+    settings: GameSettings,
+    /// Random seed for effects that should look the same for all players.
+    /// Limited to 53 bits so that it is represented exactly by JS numbers.
+    seed: u64,
+    /// Order of the last drawing change. Lets reconnecting players fetch only newer changes.
+    #[serde(skip)]
+    segment_order: u64,
+    /// Bumped on every change of the players list
+    roster_version: u64,
+    /// Last changes of the players list. Oldest changes are evicted once limit is reached.
+    /// Shared between clones of the game and copied only when it changes.
+    #[serde(skip)]
+    roster_changes: Arc<Vec<RosterChange>>,
+    /// When the first player joined the game
+    #[serde(skip)]
+    created_at: Instant,
 }
 
 impl Game {
-    fn new(id: String, player: Player) -> Self {
-        Self {
+    fn new(id: String, player: Player, config: Arc<GamesConfig>, settings: GameSettings) -> Self {
+        let mut game = Self {
             id,
             stage: GameStage::PlayerChoosing {
-                player_id: player.id.clone(),
+                player_id: player.id,
             },
-            players: vec![player],
+            players: vec![],
             history: vec![],
-        }
+            config,
+            scores: HashMap::new(),
+            settings,
+            seed: rand::thread_rng().gen::<u64>() >> 11,
+            segment_order: 0,
+            roster_version: 0,
+            roster_changes: Arc::new(vec![]),
+            created_at: Instant::now(),
+        };
+        game.add_player(player);
+        game
+    }
+
+    /// How long ago the game was created
+    pub fn age(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.created_at)
     }
 
     /// Add a player to the game
     fn add_player(&mut self, player: Player) {
         let existing = self.players.iter().find(|p| p.id == player.id);
         if existing.is_none() {
-            self.players.push(player);
+            self.players.push(player.clone());
+            self.record_roster_change(player, true);
+        }
+    }
+
+    fn record_roster_change(&mut self, player: Player, added: bool) {
+        self.roster_version += 1;
+        let roster_changes = Arc::make_mut(&mut self.roster_changes);
+        roster_changes.push(RosterChange {
+            version: self.roster_version,
+            player,
+            added,
+        });
+        if roster_changes.len() > MAX_ROSTER_CHANGES {
+            let evict = roster_changes.len() - MAX_ROSTER_CHANGES;
+            roster_changes.drain(..evict);
+        }
+    }
+
+    /// Replay recorded incoming events to reproduce a reported bug.
+    /// Players of the log must already be in the game. Events that don't change the game are skipped.
+    /// Words are redacted in the audit so they need to be filled in before replaying.
+    #[cfg(test)]
+    pub fn apply_events(&mut self, log: &[AuditEntry]) -> Result<(), String> {
+        for (i, entry) in log.iter().enumerate() {
+            let body = &entry.event["body"];
+            let field = |name: &str| {
+                body[name]
+                    .as_str()
+                    .map(String::from)
+                    .ok_or_else(|| format!("Event {} has no {}", i, name))
+            };
+            let player_id = &entry.player_id;
+            match body["type"].as_str().unwrap_or_default() {
+                "addDrawingSegment" => {
+                    let mut segment: DrawingSegment = serde_json::from_value(body.clone())
+                        .map_err(|e| format!("Event {}: {}", i, e))?;
+                    self.add_segment(player_id, &mut segment);
+                }
+                "removeDrawingSegment" => self.remove_segment(&field("segmentId")?),
+                "endSegment" => self.end_segment(player_id, &field("id")?),
+                "submitWord" => {
+                    let canvas = serde_json::from_value(body["canvas"].clone())
+                        .map_err(|e| format!("Event {}: {}", i, e))?;
+                    self.submit_word(player_id, field("word")?, canvas);
+                }
+                "guessWord" => {
+                    self.guess_word(player_id, &field("word")?);
+                }
+                "giveUp" => {
+                    self.give_up(player_id);
+                }
+                "forceNextRound" => {
+                    self.force_next_round(player_id);
+                }
+                "markClose" => {
+                    let close_player_id = field("playerId")?
+                        .parse()
+                        .map_err(|e| format!("Event {}: {}", i, e))?;
+                    self.mark_close(player_id, &close_player_id, Instant::now());
+                }
+                "leave" => {
+                    self.remove_player(player_id);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Players added and removed since a known roster version.
+    /// Full roster is returned if the changes since that version are no longer kept.
+    pub fn roster_since(&self, known_version: u64) -> RosterDelta {
+        let covered = known_version >= self.roster_version
+            || self
+                .roster_changes
+                .first()
+                .is_some_and(|change| change.version <= known_version + 1);
+        if !covered {
+            return RosterDelta {
+                version: self.roster_version,
+                full: true,
+                added: self.players.clone(),
+                removed: vec![],
+            };
         }
+
+        let mut added: Vec<Player> = vec![];
+        let mut removed: Vec<Uuid> = vec![];
+        for change in self
+            .roster_changes
+            .iter()
+            .filter(|change| change.version > known_version)
+        {
+            added.retain(|p| p.id != change.player.id);
+            removed.retain(|id| *id != change.player.id);
+            if change.added {
+                added.push(change.player.clone());
+            } else {
+                removed.push(change.player.id);
+            }
+        }
+        RosterDelta {
+            version: self.roster_version,
+            full: false,
+            added,
+            removed,
+        }
+    }
+
+    /// Find a player of the game
+    pub fn player(&self, player_id: &Uuid) -> Option<&Player> {
+        self.players.iter().find(|p| &p.id == player_id)
     }
 
     /// Remove player from the game. If player is currently drawing or choosing the word then pick another player to do that.
-    /// Return true if player was present in the game
-    fn remove_player(&mut self, remove_player_id: &Uuid) -> bool {
+    /// Return removed player if player was present in the game
+    fn remove_player(&mut self, remove_player_id: &Uuid) -> Option<Player> {
         let pos = self.players.iter().position(|p| &p.id == remove_player_id);
-
-        if pos.is_some() {
-            self.players.remove(pos.expect("Player index"));
+        let removed = pos.map(|pos| self.players.remove(pos));
+        if let Some(player) = &removed {
+            self.record_roster_change(player.clone(), false);
         }
 
         // If there is no more players left then we are done
         if self.players.is_empty() {
-            return pos.is_some();
+            return removed;
         }
 
-        // Pick next player
-        match self.stage {
-            GameStage::PlayerChoosing { player_id } if &player_id == remove_player_id => {
-                self.stage = GameStage::PlayerChoosing {
-                    player_id: self.players[0].id.clone(),
-                };
-            }
-            GameStage::PlayerDrawing { player_id, .. } if &player_id == remove_player_id => {
-                self.stage = GameStage::PlayerChoosing {
-                    player_id: self.players[0].id.clone(),
-                };
-            }
-            _ => {}
+        // Pick next player in turn order if active player is no longer in the game
+        let active_player_id = self.stage.player_id();
+        if !self.players.iter().any(|p| p.id == active_player_id) {
+            self.stage = GameStage::PlayerChoosing {
+                player_id: self.next_player_from(pos.unwrap_or(0)),
+            };
+        }
+        removed
+    }
+
+    /// First player in turn order starting from given position who is not away.
+    /// Falls back to the player at the position if everybody is away.
+    fn next_player_from(&self, pos: usize) -> Uuid {
+        let len = self.players.len();
+        (0..len)
+            .map(|i| &self.players[(pos + i) % len])
+            .find(|p| !p.away)
+            .unwrap_or(&self.players[pos % len])
+            .id
+    }
+
+    /// Set whether player is away. Choosing player who steps away passes the turn to the next player.
+    /// Return true if the game was changed.
+    pub fn set_away(&mut self, player_id: &Uuid, away: bool) -> bool {
+        let pos = match self.players.iter().position(|p| &p.id == player_id) {
+            Some(pos) if self.players[pos].away != away => pos,
+            _ => return false,
         };
-        pos.is_some()
+        self.players[pos].away = away;
+
+        let choosing =
+            matches!(self.stage, GameStage::PlayerChoosing { player_id: id } if id == *player_id);
+        if away && choosing && self.players.iter().any(|p| !p.away) {
+            self.stage = GameStage::PlayerChoosing {
+                player_id: self.next_player_from(pos + 1),
+            };
+        }
+        true
     }
 
-    /// Add drawing segment if we are in drawing stage
-    pub fn add_segment(&mut self, segment: DrawingSegment) {
-        if let GameStage::PlayerDrawing {
-            ref mut drawing, ..
-        } = &mut self.stage
-        {
-            drawing.segments.push(segment);
+    /// Add drawing segment if we are in drawing stage and this player is drawing.
+    /// Segments without an id or reusing an id seen during this turn are rejected
+    /// so that removals stay unambiguous.
+    /// Return how the segment was added or None if it was rejected.
+    pub fn add_segment(
+        &mut self,
+        drawing_player_id: &Uuid,
+        segment: &mut DrawingSegment,
+    ) -> Option<AddedSegment> {
+        let allowed_tools = &self.settings.allowed_tools;
+        if !allowed_tools.is_empty() && !allowed_tools.contains(&segment.tool()) {
+            return None;
+        }
+        if !segment.snap_stroke(&self.settings.palette) {
+            return None;
+        }
+        let merge_within = self.config.merge_segments_within;
+        let max_points = self.config.max_drawing_points;
+        match &mut self.stage {
+            GameStage::PlayerDrawing {
+                player_id,
+                ref mut drawing,
+                ..
+            } if player_id == drawing_player_id
+                && segment.is_valid()
+                && drawing.accepts_id(&segment.id) =>
+            {
+                let now = Instant::now();
+                let recent = match (merge_within, drawing.last_segment_at) {
+                    (Some(within), Some(last_at)) => now.duration_since(last_at) <= within,
+                    _ => false,
+                };
+                drawing.last_segment_at = Some(now);
+                self.segment_order += 1;
+                segment.order = self.segment_order;
+                segment.created_order = self.segment_order;
+                drawing.timeline.push(TimedSegment {
+                    offset: now.duration_since(drawing.started_at),
+                    segment: segment.clone(),
+                });
+
+                let mut added = AddedSegment::Stored;
+                if recent {
+                    // Merge only into the stroke that is still being drawn. Otherwise dropping
+                    // the unfinished stroke would remove a finished one too.
+                    let uncommitted = drawing.uncommitted_segment_id.clone();
+                    let last = drawing.segments.last_mut().filter(|last| {
+                        uncommitted.is_some_and(|id| last.id == id || last.merged_ids.contains(&id))
+                    });
+                    if let Some(last) = last {
+                        if last.merge(segment) {
+                            last.order = segment.order;
+                            added = AddedSegment::Merged {
+                                into_segment_id: last.id.clone(),
+                            };
+                        }
+                    }
+                }
+                if added == AddedSegment::Stored {
+                    drawing.segments.push(segment.clone());
+                }
+                drawing.uncommitted_segment_id = Some(segment.id.clone());
+
+                if let Some(max_points) = max_points {
+                    drawing.compact(max_points);
+                }
+                Some(added)
+            }
+            _ => None,
         }
     }
 
@@ -87,119 +612,664 @@ impl Game {
             ref mut drawing, ..
         } = &mut self.stage
         {
-            drawing.segments.retain(|s| s.id != segment_id);
+            let is_removed = |s: &DrawingSegment| {
+                s.id == segment_id || s.merged_ids.iter().any(|id| id == segment_id)
+            };
+
+            // Forget removed segments together with the segments merged into them
+            let mut removed_ids = vec![segment_id.to_string()];
+            for s in drawing.segments.iter().filter(|s| is_removed(s)) {
+                for id in std::iter::once(&s.id).chain(&s.merged_ids) {
+                    if !removed_ids.contains(id) {
+                        removed_ids.push(id.clone());
+                    }
+                }
+            }
+            drawing
+                .timeline
+                .retain(|timed| !removed_ids.contains(&timed.segment.id));
+
+            let before = drawing.segments.len();
+            drawing.segments.retain(|s| !is_removed(s));
+            if drawing.segments.len() != before {
+                self.segment_order += 1;
+                let order = self.segment_order;
+                drawing
+                    .removed
+                    .extend(removed_ids.into_iter().map(|id| (order, id)));
+            }
         }
     }
 
+    /// Whether the phrase contains any blocked entry of the config.
+    /// Entries of several words match the same words in a row, whatever separates them.
+    fn is_blocked(&self, phrase: &str) -> bool {
+        let words = |text: &str| -> Vec<String> {
+            text.to_lowercase()
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|word| !word.is_empty())
+                .map(String::from)
+                .collect()
+        };
+        let phrase = words(phrase);
+        self.config.blocked_words.iter().any(|blocked| {
+            let blocked = words(blocked);
+            !blocked.is_empty()
+                && phrase
+                    .windows(blocked.len())
+                    .any(|w| w == blocked.as_slice())
+        })
+    }
+
     /// Submit a word to draw. Transitions to drawing stage if this player was allowed to do that.
-    /// Return true if transitioned.
     pub fn submit_word(
         &mut self,
         submitting_player_id: &Uuid,
         word: String,
         canvas: CanvasSize,
-    ) -> bool {
-        match self.stage {
-            GameStage::PlayerChoosing { player_id } if submitting_player_id == &player_id => {
-                // continue
-                self.stage = GameStage::PlayerDrawing {
-                    player_id,
-                    word: word.trim().to_string(),
-                    drawing: Drawing {
-                        canvas,
-                        segments: vec![],
+    ) -> SubmitResult {
+        let player_id = match self.stage {
+            GameStage::PlayerChoosing { player_id } => player_id,
+            _ => return SubmitResult::WrongStage,
+        };
+        if submitting_player_id != &player_id {
+            return SubmitResult::NotYourTurn;
+        }
+
+        let word = word.trim();
+        if word.chars().count() > MAX_WORD_LEN {
+            // Word is too long to be drawn or masked
+            return SubmitResult::TooLong;
+        }
+        if !canvas.is_valid() {
+            return SubmitResult::InvalidCanvas;
+        }
+        if self.is_blocked(word) {
+            return SubmitResult::Profanity;
+        }
+
+        self.stage = GameStage::PlayerDrawing {
+            player_id,
+            word: word.to_string(),
+            guesses: HashMap::new(),
+            last_guesses: HashMap::new(),
+            close_guessers: HashSet::new(),
+            gave_up: HashSet::new(),
+            drawing: Drawing {
+                canvas,
+                segments: vec![],
+                started_at: Instant::now(),
+                last_segment_at: None,
+                drawer_active: false,
+                last_marked_close_at: None,
+                timeline: vec![],
+                first_order: self.segment_order + 1,
+                removed: vec![],
+                no_guessers_since: None,
+                uncommitted_segment_id: None,
+                typing: HashMap::new(),
+            },
+        };
+        SubmitResult::Accepted
+    }
+
+    /// Guess a word. Transitions to choose a word stage if guess was correct.
+    pub fn guess_word(&mut self, guessing_player_id: &Uuid, guess: &str) -> GuessResult {
+        let max_guesses = self.settings.max_guesses;
+        // Guesses that normalize the same way are either both right or both wrong
+        let normalized = if self.settings.strict_match {
+            guess.trim().to_string()
+        } else {
+            guess.to_lowercase()
+        };
+        let (word, round) = match &mut self.stage {
+            GameStage::PlayerDrawing { player_id, .. } if player_id == guessing_player_id => {
+                // Drawing player knows the word
+                return GuessResult::NotYourTurn;
+            }
+            GameStage::PlayerDrawing {
+                word,
+                player_id,
+                drawing,
+                guesses,
+                last_guesses,
+                close_guessers,
+                ..
+            } => {
+                let attempts = guesses.entry(*guessing_player_id).or_insert(0);
+                if max_guesses.is_some_and(|max| *attempts >= max) {
+                    return GuessResult::OutOfGuesses;
+                }
+                if last_guesses.get(guessing_player_id) == Some(&normalized) {
+                    return GuessResult::Repeated;
+                }
+                last_guesses.insert(*guessing_player_id, normalized);
+                *attempts += 1;
+                if edit_distance(&word.to_lowercase(), &guess.to_lowercase())
+                    <= CLOSE_GUESS_DISTANCE
+                {
+                    close_guessers.insert(*guessing_player_id);
+                }
+                (
+                    word.clone(),
+                    Round {
+                        drawing_player_id: *player_id,
+                        guessing_player_id: *guessing_player_id,
+                        time_taken: drawing.started_at.elapsed(),
                     },
-                };
+                )
+            }
+            _ => return GuessResult::WrongStage,
+        };
+        let matches = if self.settings.strict_match {
+            word == guess.trim()
+        } else {
+            word.to_lowercase() == guess.to_lowercase()
+        };
+        if !matches {
+            return GuessResult::Wrong;
+        }
+
+        self.finish_turn(self.player(guessing_player_id).cloned());
+        for (player_id, points) in self.settings.scoring.award(&round) {
+            *self.scores.entry(player_id).or_insert(0) += points;
+        }
+
+        // Go to next stage
+        self.end_round(*guessing_player_id, word, Some(*guessing_player_id));
+        GuessResult::Correct
+    }
+
+    /// Drawing player has finished the segment
+    pub fn end_segment(&mut self, drawing_player_id: &Uuid, segment_id: &str) {
+        if let GameStage::PlayerDrawing {
+            player_id, drawing, ..
+        } = &mut self.stage
+        {
+            if player_id == drawing_player_id
+                && drawing.uncommitted_segment_id.as_deref() == Some(segment_id)
+            {
+                drawing.uncommitted_segment_id = None;
+            }
+        }
+    }
+
+    /// Remove the segment that drawing player didn't finish if enabled in the config.
+    /// Return id of the removed segment.
+    pub fn drop_uncommitted_segment(&mut self, drawing_player_id: &Uuid) -> Option<String> {
+        if !self.config.drop_uncommitted_segments {
+            return None;
+        }
+        let segment_id = match &mut self.stage {
+            GameStage::PlayerDrawing {
+                player_id, drawing, ..
+            } if player_id == drawing_player_id => drawing.uncommitted_segment_id.take()?,
+            _ => return None,
+        };
+        self.remove_segment(&segment_id);
+        Some(segment_id)
+    }
+
+    /// Drawing player has disconnected. Apply disconnect policy from the settings.
+    /// Return true if the game was changed.
+    pub fn drawer_disconnected(&mut self, player_id: &Uuid) -> bool {
+        let drawing = matches!(
+            &self.stage,
+            GameStage::PlayerDrawing { player_id: drawer_id, .. } if drawer_id == player_id
+        );
+        if !drawing {
+            return false;
+        }
+
+        match self.settings.drawer_disconnect {
+            // Drawing stays as is until the player comes back or is removed
+            DrawerDisconnect::Wait => false,
+            DrawerDisconnect::Forfeit => {
+                self.reveal_word();
                 true
             }
-            _ => {
-                // This player cannot submit a word to draw
-                false
+        }
+    }
+
+    /// Guessing player gives up. Round ends once all guessing players have given up.
+    pub fn give_up(&mut self, player_id: &Uuid) -> GiveUpResult {
+        let players = &self.players;
+        match &mut self.stage {
+            GameStage::PlayerDrawing {
+                player_id: drawer_id,
+                ..
+            } if drawer_id == player_id => GiveUpResult::NotAllowed,
+            GameStage::PlayerDrawing {
+                player_id: drawer_id,
+                gave_up,
+                ..
+            } => {
+                gave_up.insert(*player_id);
+                // Players who gave up and left don't count
+                let everybody = players
+                    .iter()
+                    .filter(|p| p.id != *drawer_id)
+                    .all(|p| gave_up.contains(&p.id));
+                if !everybody {
+                    return GiveUpResult::Waiting;
+                }
+                self.reveal_word();
+                GiveUpResult::RoundEnded
             }
+            _ => GiveUpResult::NotAllowed,
         }
     }
 
-    /// Guess a word. Transitions to choose a word stage if guess was correct.
-    /// Return true if transitioned.
-    pub fn guess_word(&mut self, guessing_player_id: &Uuid, guess: &str) -> bool {
-        match &self.stage {
-            GameStage::PlayerDrawing { word, .. }
-                if word.to_lowercase() == guess.to_lowercase() =>
-            {
-                // Populate history
-                self.history.push(Turn {
-                    word: word.clone(),
-                    player_guessed: self
-                        .players
-                        .iter()
-                        .find(|p| &p.id == guessing_player_id)
-                        .cloned(),
-                });
+    /// Host is the player who has been in the game the longest
+    pub fn host_id(&self) -> Option<Uuid> {
+        self.players.first().map(|p| p.id)
+    }
+
+    /// Host ends the round without a winner, e.g. when the round is stuck.
+    /// Return true if the round has ended.
+    pub fn force_next_round(&mut self, player_id: &Uuid) -> bool {
+        if self.host_id() != Some(*player_id)
+            || !matches!(self.stage, GameStage::PlayerDrawing { .. })
+        {
+            return false;
+        }
+        self.reveal_word();
+        true
+    }
+
+    /// End the round without a winner. Reveal the word in history and pass the turn
+    /// to the player after the drawing one.
+    fn reveal_word(&mut self) {
+        let drawer_id = self.stage.player_id();
+        let word = match &self.stage {
+            GameStage::PlayerDrawing { word, .. } => word.clone(),
+            _ => return,
+        };
+        self.finish_turn(None);
+        let pos = self.players.iter().position(|p| p.id == drawer_id);
+        let next_player_id = self.next_player_from(pos.map_or(0, |pos| pos + 1));
+        self.end_round(next_player_id, word, None);
+    }
+
+    /// Show round summary if enabled in the settings, otherwise let next player choose a word.
+    fn end_round(&mut self, next_player_id: Uuid, word: String, guessed_by: Option<Uuid>) {
+        self.stage = match self.settings.round_summary_secs {
+            Some(secs) => GameStage::RoundSummary {
+                player_id: next_player_id,
+                word,
+                guessed_by,
+                until: Instant::now() + Duration::from_secs(secs),
+            },
+            None => GameStage::PlayerChoosing {
+                player_id: next_player_id,
+            },
+        };
+    }
 
-                // Go to next stage
+    /// Let next player choose a word once round summary has been shown long enough.
+    /// Return true if the game has advanced.
+    pub fn advance_round_summary(&mut self, now: Instant) -> bool {
+        match &self.stage {
+            GameStage::RoundSummary {
+                player_id, until, ..
+            } if now >= *until => {
                 self.stage = GameStage::PlayerChoosing {
-                    player_id: guessing_player_id.clone(),
+                    player_id: *player_id,
                 };
                 true
             }
-            _ => {
-                // Wrong guess or state
-                false
+            _ => false,
+        }
+    }
+
+    /// Update whether enough guessing players are connected.
+    /// Round ends if not enough can guess for too long and resumes once they are back.
+    /// Without a configured grace period the round ends right away, but only for rooms with `min_guessers`.
+    /// Return true if the round has ended and false if the game has resumed.
+    pub fn update_guessers_presence(&mut self, connected: bool, now: Instant) -> Option<bool> {
+        let grace = match self.config.end_round_without_guessers_after {
+            Some(grace) => grace,
+            None if self.settings.min_guessers.is_some() => Duration::from_secs(0),
+            None => return None,
+        };
+
+        match &mut self.stage {
+            GameStage::PlayerDrawing {
+                player_id, drawing, ..
+            } => {
+                if connected {
+                    drawing.no_guessers_since = None;
+                    return None;
+                }
+                let since = *drawing.no_guessers_since.get_or_insert(now);
+                if now.duration_since(since) < grace {
+                    return None;
+                }
+
+                let player_id = *player_id;
+                self.finish_turn(None);
+                self.stage = GameStage::WaitingForPlayers { player_id };
+                Some(true)
+            }
+            GameStage::WaitingForPlayers { player_id } if connected => {
+                self.stage = GameStage::PlayerChoosing {
+                    player_id: *player_id,
+                };
+                Some(false)
             }
+            _ => None,
+        }
+    }
+
+    /// Move current drawing into history
+    fn finish_turn(&mut self, player_guessed: Option<Player>) {
+        let (word, timeline, time_taken) = match &mut self.stage {
+            GameStage::PlayerDrawing { word, drawing, .. } => (
+                word.clone(),
+                std::mem::take(&mut drawing.timeline),
+                drawing.started_at.elapsed(),
+            ),
+            _ => return,
+        };
+
+        self.history.push(Turn {
+            word,
+            player_guessed,
+            timeline: Arc::new(timeline),
+            time_taken,
+        });
+        if self.history.len() > self.config.history_limit {
+            let evict = self.history.len() - self.config.history_limit;
+            self.history.drain(..evict);
         }
     }
 
     /// Ask for a tip with a word. Return a tip if
     pub fn ask_word_tip(&mut self) -> Option<String> {
+        if !self.hints_enabled() {
+            return None;
+        }
+        self.word_mask()
+    }
+
+    /// Whether guessing players may see letters of the word
+    pub fn hints_enabled(&self) -> bool {
+        !self.settings.hints_disabled
+    }
+
+    /// Word with hidden letters if there is a word to guess
+    pub fn word_mask(&self) -> Option<String> {
         match &self.stage {
             GameStage::PlayerDrawing { word, .. } => {
-                // Show first letter and last letters with hidden letters in between (e.g "Apple" => "a***e")
-                let word = word.to_lowercase();
+                // Show first letter and last letters of every word with hidden letters in between
+                // (e.g "Apple pie" => "a***e p*e")
                 let help = word
-                    .chars()
-                    .enumerate()
-                    .map(|(i, c)| {
-                        if (i == 0) || (i == word.len() - 1) {
-                            c
-                        } else {
-                            '*'
-                        }
-                    })
-                    .collect();
+                    .to_lowercase()
+                    .split(' ')
+                    .map(mask_word)
+                    .collect::<Vec<_>>()
+                    .join(" ");
                 Some(help)
             }
             _ => None,
         }
     }
 
-    /// Iterate over drawing segments if there is a drawing
-    pub fn iter_drawing(&self, cb: impl Fn(&DrawingSegment)) {
-        if let GameStage::PlayerDrawing { drawing, .. } = &self.stage {
-            for segment in &drawing.segments {
-                cb(segment);
+    /// How many guessers were close to the word. Only available when enabled in settings.
+    pub fn guess_progress(&self) -> Option<GuessProgress> {
+        match &self.stage {
+            GameStage::PlayerDrawing { close_guessers, .. } if self.settings.guess_progress => {
+                Some(GuessProgress {
+                    close_count: close_guessers.len(),
+                    total_guessers: self.players.len().saturating_sub(1),
+                })
             }
+            _ => None,
         }
     }
-}
 
-#[derive(Debug, Serialize, Clone)]
-#[serde(rename_all = "camelCase")]
-#[serde(tag = "type")]
-enum GameStage {
-    /// A player is choosing a word
-    #[serde(rename_all = "camelCase")]
-    PlayerChoosing { player_id: Uuid },
+    /// Drawing player marks that a guessing player is close to the answer.
+    /// Return whether the mark was accepted.
+    pub fn mark_close(&mut self, drawing_player_id: &Uuid, player_id: &Uuid, now: Instant) -> bool {
+        if drawing_player_id == player_id || self.player(player_id).is_none() {
+            return false;
+        }
 
-    /// A player is drawing while others are guessing
-    #[serde(rename_all = "camelCase")]
-    PlayerDrawing {
-        player_id: Uuid,
-        #[serde(skip)]
-        word: String,
-        drawing: Drawing,
-        // TODO: keep track of guess attempts per player
-    },
-}
+        match &mut self.stage {
+            GameStage::PlayerDrawing {
+                player_id: drawer_id,
+                drawing,
+                ..
+            } if drawer_id == drawing_player_id => {
+                let too_soon = drawing
+                    .last_marked_close_at
+                    .is_some_and(|at| now.duration_since(at) < MARK_CLOSE_INTERVAL);
+                if too_soon {
+                    return false;
+                }
+                drawing.last_marked_close_at = Some(now);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Update whether drawing player is actively drawing judging by the time of the last segment.
+    /// Return new activity state if it changed.
+    pub fn drawer_activity(&mut self, now: Instant) -> Option<bool> {
+        if let GameStage::PlayerDrawing { drawing, .. } = &mut self.stage {
+            let active = drawing
+                .last_segment_at
+                .is_some_and(|at| now.duration_since(at) < DRAWER_IDLE_AFTER);
+            if active != drawing.drawer_active {
+                drawing.drawer_active = active;
+                return Some(active);
+            }
+        }
+        None
+    }
+
+    /// Guessing player starts or stops typing a guess.
+    /// Repeated updates only keep the indicator alive.
+    /// Return true if others should be notified or None if the player can't guess.
+    pub fn set_typing(&mut self, player_id: &Uuid, typing: bool, now: Instant) -> Option<bool> {
+        let is_player = self.players.iter().any(|p| p.id == *player_id);
+        match &mut self.stage {
+            GameStage::PlayerDrawing {
+                player_id: drawing_player_id,
+                drawing,
+                ..
+            } if is_player && drawing_player_id != player_id => {
+                if typing {
+                    Some(drawing.typing.insert(*player_id, now).is_none())
+                } else {
+                    Some(drawing.typing.remove(player_id).is_some())
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Stop typing indicators that weren't updated recently. Return players who stopped typing.
+    pub fn expire_typing(&mut self, now: Instant) -> Vec<Uuid> {
+        let drawing = match &mut self.stage {
+            GameStage::PlayerDrawing { drawing, .. } => drawing,
+            _ => return vec![],
+        };
+        let expired: Vec<Uuid> = drawing
+            .typing
+            .iter()
+            .filter(|(_, at)| now.duration_since(**at) >= TYPING_EXPIRES_AFTER)
+            .map(|(player_id, _)| *player_id)
+            .collect();
+        for player_id in &expired {
+            drawing.typing.remove(player_id);
+        }
+        expired
+    }
+
+    /// Forfeit the round if drawing player hasn't drawn anything for too long.
+    /// Return true if the round has ended.
+    pub fn forfeit_idle_drawer(&mut self, now: Instant) -> bool {
+        let threshold = match self.settings.forfeit_idle_drawer_secs {
+            Some(secs) => Duration::from_secs(secs),
+            None => return false,
+        };
+        let player_id = match &self.stage {
+            GameStage::PlayerDrawing {
+                player_id, drawing, ..
+            } if drawing.last_segment_at.is_none()
+                && now.duration_since(drawing.started_at) >= threshold =>
+            {
+                *player_id
+            }
+            _ => return false,
+        };
+
+        if let Some(score) = self.scores.get_mut(&player_id) {
+            *score = score.saturating_sub(self.settings.idle_drawer_penalty);
+        }
+        self.reveal_word();
+        true
+    }
+
+    /// Whether a player is drawing at the moment
+    pub fn is_drawing(&self) -> bool {
+        matches!(self.stage, GameStage::PlayerDrawing { .. })
+    }
+
+    /// Player who is choosing or drawing
+    pub fn active_player_id(&self) -> Uuid {
+        self.stage.player_id()
+    }
+
+    /// Outcome of the last finished turn
+    pub fn last_turn_outcome(&self) -> Option<TurnOutcome> {
+        self.history.last().map(|turn| TurnOutcome {
+            word: turn.word.clone(),
+            guessed: turn.player_guessed.is_some(),
+            time_taken: turn.time_taken,
+        })
+    }
+
+    /// Segments of a past turn with the time they were drawn at
+    pub fn turn_timeline(&self, turn_index: usize) -> Option<Arc<Vec<TimedSegment>>> {
+        self.history
+            .get(turn_index)
+            .map(|turn| turn.timeline.clone())
+    }
+
+    /// Render current drawing as SVG document if there is a drawing
+    pub fn drawing_svg(&self) -> Option<String> {
+        match &self.stage {
+            GameStage::PlayerDrawing { drawing, .. } => Some(drawing.to_svg()),
+            _ => None,
+        }
+    }
+
+    /// Drawing changes that happened after given segment order if there is a drawing
+    pub fn drawing_changes(&self, known_order: u64) -> Option<DrawingChanges> {
+        let drawing = match &self.stage {
+            GameStage::PlayerDrawing { drawing, .. } => drawing,
+            _ => return None,
+        };
+
+        if known_order < drawing.first_order {
+            // Known segments are from another drawing
+            return Some(DrawingChanges {
+                clear: true,
+                removed_ids: vec![],
+                segments: drawing.segments.clone(),
+            });
+        }
+
+        let mut changes = DrawingChanges {
+            clear: false,
+            removed_ids: drawing
+                .removed
+                .iter()
+                .filter(|(order, _)| *order > known_order)
+                .map(|(_, id)| id.clone())
+                .collect(),
+            segments: vec![],
+        };
+        for segment in drawing.segments.iter().filter(|s| s.order > known_order) {
+            if segment.created_order <= known_order {
+                // Segment was extended by a merge. Replace it as a whole.
+                changes.removed_ids.push(segment.id.clone());
+            }
+            changes.segments.push(segment.clone());
+        }
+        Some(changes)
+    }
+
+    /// Iterate over drawing segments if there is a drawing
+    pub fn iter_drawing(&self, mut cb: impl FnMut(&DrawingSegment)) {
+        if let GameStage::PlayerDrawing { drawing, .. } = &self.stage {
+            for segment in &drawing.segments {
+                cb(segment);
+            }
+        }
+    }
+}
+
+// Stage is stored once per game so boxing the drawing is not worth it
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+#[serde(tag = "type")]
+enum GameStage {
+    /// A player is choosing a word
+    #[serde(rename_all = "camelCase")]
+    PlayerChoosing { player_id: Uuid },
+
+    /// A player is drawing while others are guessing
+    #[serde(rename_all = "camelCase")]
+    PlayerDrawing {
+        player_id: Uuid,
+        #[serde(skip)]
+        word: String,
+        drawing: Drawing,
+        /// Number of guess attempts per player
+        #[serde(skip)]
+        guesses: HashMap<Uuid, u32>,
+        /// Last normalized guess per player
+        #[serde(skip)]
+        last_guesses: HashMap<Uuid, String>,
+        /// Players whose guesses were close to the word
+        #[serde(skip)]
+        close_guessers: HashSet<Uuid>,
+        /// Guessing players who gave up
+        #[serde(skip)]
+        gave_up: HashSet<Uuid>,
+    },
+
+    /// Nobody is connected to guess. Player will choose a word once others are back.
+    #[serde(rename_all = "camelCase")]
+    WaitingForPlayers { player_id: Uuid },
+
+    /// Round has ended. Player will choose a word once the summary has been shown.
+    #[serde(rename_all = "camelCase")]
+    RoundSummary {
+        player_id: Uuid,
+        word: String,
+        guessed_by: Option<Uuid>,
+        #[serde(skip)]
+        until: Instant,
+    },
+}
+
+impl GameStage {
+    /// Player who is choosing or drawing
+    fn player_id(&self) -> Uuid {
+        match self {
+            GameStage::PlayerChoosing { player_id } => *player_id,
+            GameStage::PlayerDrawing { player_id, .. } => *player_id,
+            GameStage::WaitingForPlayers { player_id } => *player_id,
+            GameStage::RoundSummary { player_id, .. } => *player_id,
+        }
+    }
+}
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -207,6 +1277,36 @@ pub struct Drawing {
     pub canvas: CanvasSize,
     #[serde(skip)]
     pub segments: Vec<DrawingSegment>,
+    /// When the drawing has started
+    #[serde(skip)]
+    started_at: Instant,
+    /// When the last segment was received
+    #[serde(skip)]
+    last_segment_at: Option<Instant>,
+    /// Whether drawing player was drawing recently
+    #[serde(skip)]
+    drawer_active: bool,
+    /// When drawing player marked a guess as close the last time
+    #[serde(skip)]
+    last_marked_close_at: Option<Instant>,
+    /// Segments in the order they were received. Kept for replays.
+    #[serde(skip)]
+    timeline: Vec<TimedSegment>,
+    /// Order of the first change of this drawing
+    #[serde(skip)]
+    first_order: u64,
+    /// Ids of removed segments together with the order of removal
+    #[serde(skip)]
+    removed: Vec<(u64, String)>,
+    /// When the last guessing player disconnected
+    #[serde(skip)]
+    no_guessers_since: Option<Instant>,
+    /// Last segment that drawing player hasn't finished yet
+    #[serde(skip)]
+    uncommitted_segment_id: Option<String>,
+    /// Guessing players who are typing a guess together with the time of the last update
+    #[serde(skip)]
+    typing: HashMap<Uuid, Instant>,
 }
 
 // Implement custom Clone to skip cloning segments
@@ -215,10 +1315,206 @@ impl Clone for Drawing {
         Self {
             canvas: self.canvas.clone(),
             segments: vec![],
+            started_at: self.started_at,
+            last_segment_at: self.last_segment_at,
+            drawer_active: self.drawer_active,
+            last_marked_close_at: self.last_marked_close_at,
+            timeline: vec![],
+            first_order: self.first_order,
+            removed: vec![],
+            no_guessers_since: self.no_guessers_since,
+            uncommitted_segment_id: self.uncommitted_segment_id.clone(),
+            typing: self.typing.clone(),
+        }
+    }
+}
+
+impl Drawing {
+    /// Whether a new segment can use this id. Ids must be unique within the turn.
+    fn accepts_id(&self, segment_id: &str) -> bool {
+        let live = self
+            .segments
+            .iter()
+            .any(|s| s.id == segment_id || s.merged_ids.iter().any(|id| id == segment_id));
+        !segment_id.is_empty() && !live && !self.removed.iter().any(|(_, id)| id == segment_id)
+    }
+
+    /// Simplify oldest segments (and their replay copies) to fit into the points budget.
+    fn compact(&mut self, max_points: usize) {
+        let mut segments: Vec<_> = self.segments.iter_mut().collect();
+        let mut timeline: Vec<_> = self.timeline.iter_mut().map(|t| &mut t.segment).collect();
+        let before = count_points(&segments) + count_points(&timeline);
+        simplify_oldest(&mut segments, max_points);
+        simplify_oldest(&mut timeline, max_points);
+        let after = count_points(&segments) + count_points(&timeline);
+        if after < before {
+            log::info!("Compacted drawing from {} to {} points", before, after);
+        }
+    }
+
+    /// Render drawing as SVG document. Lines become polylines and stamps become text.
+    fn to_svg(&self) -> String {
+        let mut svg = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+            w = self.canvas.width,
+            h = self.canvas.height
+        );
+        for segment in &self.segments {
+            match &segment.kind {
+                SegmentKind::Line {
+                    stroke,
+                    line_width,
+                    points,
+                } => {
+                    let points: Vec<String> =
+                        points.iter().map(|p| format!("{},{}", p.x, p.y)).collect();
+                    svg.push_str(&format!(
+                        r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="{}" stroke-linecap="round" stroke-linejoin="round"/>"#,
+                        points.join(" "),
+                        escape_xml(stroke),
+                        line_width
+                    ));
+                }
+                SegmentKind::Stamp { stamp, at, size } => {
+                    svg.push_str(&format!(
+                        r#"<text x="{}" y="{}" font-size="{}" text-anchor="middle" dominant-baseline="central">{}</text>"#,
+                        at.x,
+                        at.y,
+                        size,
+                        escape_xml(stamp)
+                    ));
+                }
+            }
         }
+        svg.push_str("</svg>");
+        svg
     }
 }
 
+/// Escape special characters to use the value in XML
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Outcome of submitting a word to draw
+#[derive(Debug, PartialEq)]
+pub enum SubmitResult {
+    /// Player is now drawing the word
+    Accepted,
+    /// Nobody is choosing a word at the moment
+    WrongStage,
+    /// Another player is choosing a word
+    NotYourTurn,
+    /// Word is longer than allowed
+    TooLong,
+    /// Canvas is too small or too big
+    InvalidCanvas,
+    /// Word is blocked as offensive
+    Profanity,
+}
+
+/// Outcome of guessing a word
+#[derive(Debug, PartialEq)]
+pub enum GuessResult {
+    /// Guess was correct and turn passes to the guessing player
+    Correct,
+    /// Guess was wrong
+    Wrong,
+    /// There is nothing to guess at the moment
+    WrongStage,
+    /// Drawing player cannot guess their own word
+    NotYourTurn,
+    /// Player has used all guesses for this round
+    OutOfGuesses,
+    /// Same wrong guess as the previous one of the player. Not counted as an attempt.
+    Repeated,
+}
+
+/// Anonymized progress of guessing players
+#[derive(Debug, PartialEq, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GuessProgress {
+    pub close_count: usize,
+    pub total_guessers: usize,
+}
+
+/// Incoming event recorded for bug reports
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub player_id: Uuid,
+    /// Milliseconds since Unix epoch
+    pub at: u64,
+    /// Event as received with words redacted
+    pub event: serde_json::Value,
+}
+
+/// Report of an abusive player
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub reporter_id: Uuid,
+    pub player_id: Uuid,
+    pub reason: String,
+    /// Milliseconds since Unix epoch
+    pub at: u64,
+}
+
+/// Change of the players list
+#[derive(Debug, Clone)]
+struct RosterChange {
+    version: u64,
+    player: Player,
+    /// Whether the player was added or removed
+    added: bool,
+}
+
+/// Changes a client needs to apply to a players list they saw before
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RosterDelta {
+    /// Current roster version
+    pub version: u64,
+    /// Whether added players is the full roster and the known list should be replaced
+    pub full: bool,
+    pub added: Vec<Player>,
+    pub removed: Vec<Uuid>,
+}
+
+/// Changes a player needs to apply to a drawing they saw before
+#[derive(Debug)]
+pub struct DrawingChanges {
+    /// Whether known drawing should be cleared first
+    pub clear: bool,
+    pub removed_ids: Vec<String>,
+    pub segments: Vec<DrawingSegment>,
+}
+
+/// Outcome of giving up
+#[derive(Debug, PartialEq)]
+pub enum GiveUpResult {
+    /// Some guessing players haven't given up yet
+    Waiting,
+    /// Everybody gave up and the word was revealed
+    RoundEnded,
+    /// Only guessing players can give up while somebody is drawing
+    NotAllowed,
+}
+
+/// Outcome of adding a drawing segment
+#[derive(Debug, PartialEq)]
+pub enum AddedSegment {
+    /// Segment was stored as is
+    Stored,
+    /// Segment points were appended to an existing segment
+    Merged { into_segment_id: String },
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct CanvasSize {
@@ -226,11 +1522,21 @@ pub struct CanvasSize {
     pub height: u32,
 }
 
+impl CanvasSize {
+    /// Return whether both dimensions are within allowed range
+    fn is_valid(&self) -> bool {
+        let range = MIN_CANVAS_SIZE..=MAX_CANVAS_SIZE;
+        range.contains(&self.width) && range.contains(&self.height)
+    }
+}
+
 #[derive(Debug, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Player {
     pub id: Uuid,
     pub nickname: String,
+    /// Player stepped away and shouldn't be picked to draw
+    pub away: bool,
 }
 
 /// Turn describes historic turn of the game.
@@ -239,15 +1545,208 @@ pub struct Player {
 struct Turn {
     word: String,
     player_guessed: Option<Player>,
+    /// Shared so that cloning the game doesn't copy the drawing
+    #[serde(skip)]
+    timeline: Arc<Vec<TimedSegment>>,
+    /// How long the turn lasted
+    #[serde(skip)]
+    time_taken: Duration,
 }
 
+/// How a finished turn went
+#[derive(Debug)]
+pub struct TurnOutcome {
+    pub word: String,
+    pub guessed: bool,
+    pub time_taken: Duration,
+}
+
+/// Drawing segment together with the time since the start of the drawing
+#[derive(Debug, Clone)]
+pub struct TimedSegment {
+    pub offset: Duration,
+    pub segment: DrawingSegment,
+}
+
+/// Stamps that players are allowed to place on the drawing
+const ALLOWED_STAMPS: &[&str] = &[
+    "⭐", "❤️", "😀", "😢", "👍", "🔥", "🌲", "🌞", "🌙", "☁️", "●", "■", "▲",
+];
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DrawingSegment {
     id: String,
-    stroke: String,
-    line_width: i32,
-    points: Vec<Point>,
+    #[serde(flatten)]
+    kind: SegmentKind,
+    /// Ids of segments that were merged into this one
+    #[serde(skip)]
+    merged_ids: Vec<String>,
+    /// Order of the last change of this segment. Assigned by the server.
+    #[serde(default, skip_deserializing)]
+    order: u64,
+    /// Order of the segment when it was added
+    #[serde(skip)]
+    created_order: u64,
+}
+
+impl DrawingSegment {
+    fn point_count(&self) -> usize {
+        match &self.kind {
+            SegmentKind::Line { points, .. } => points.len(),
+            SegmentKind::Stamp { .. } => 1,
+        }
+    }
+
+    /// Drop every other point of a line keeping its ends. Return true if any point was dropped.
+    fn simplify(&mut self) -> bool {
+        match &mut self.kind {
+            SegmentKind::Line { points, .. } if points.len() > 2 => {
+                let last = points.len() - 1;
+                let mut i = 0;
+                points.retain(|_| {
+                    let keep = i % 2 == 0 || i == last;
+                    i += 1;
+                    keep
+                });
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Append points of the other line segment if it has the same style.
+    /// Return true if merged.
+    fn merge(&mut self, other: &DrawingSegment) -> bool {
+        match (&mut self.kind, &other.kind) {
+            (
+                SegmentKind::Line {
+                    stroke,
+                    line_width,
+                    points,
+                },
+                SegmentKind::Line {
+                    stroke: other_stroke,
+                    line_width: other_line_width,
+                    points: other_points,
+                },
+            ) if stroke == other_stroke && line_width == other_line_width => {
+                points.extend(other_points.iter().cloned());
+                self.merged_ids.push(other.id.clone());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Tool used to draw the segment
+    fn tool(&self) -> Tool {
+        match &self.kind {
+            SegmentKind::Line { .. } => Tool::Pen,
+            SegmentKind::Stamp { .. } => Tool::Stamp,
+        }
+    }
+
+    /// Return whether segment can be placed on the drawing
+    fn is_valid(&self) -> bool {
+        match &self.kind {
+            SegmentKind::Line { .. } => true,
+            SegmentKind::Stamp { stamp, .. } => ALLOWED_STAMPS.contains(&stamp.as_str()),
+        }
+    }
+
+    /// Replace line color with the nearest color from the palette.
+    /// Return false if the color couldn't be read.
+    fn snap_stroke(&mut self, palette: &[String]) -> bool {
+        let stroke = match &mut self.kind {
+            SegmentKind::Line { stroke, .. } if !palette.is_empty() => stroke,
+            _ => return true,
+        };
+        let (r, g, b) = match parse_color(stroke) {
+            Some(color) => color,
+            None => return false,
+        };
+        let distance = |(r2, g2, b2): (u8, u8, u8)| {
+            let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+            d(r, r2) + d(g, g2) + d(b, b2)
+        };
+        let nearest = palette
+            .iter()
+            .filter_map(|color| parse_color(color).map(|rgb| (color, distance(rgb))))
+            .min_by_key(|(_, distance)| *distance);
+        match nearest {
+            Some((color, _)) => {
+                *stroke = color.clone();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Read a CSS color in "rgb(r, g, b)" or "#rrggbb" format
+fn parse_color(color: &str) -> Option<(u8, u8, u8)> {
+    let color = color.trim();
+    if let Some(hex) = color.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        return Some((channel(0)?, channel(2)?, channel(4)?));
+    }
+    let channels = color.strip_prefix("rgb(")?.strip_suffix(')')?;
+    let mut channels = channels.split(',').map(|c| c.trim().parse::<u8>().ok());
+    let rgb = (channels.next()??, channels.next()??, channels.next()??);
+    match channels.next() {
+        None => Some(rgb),
+        Some(_) => None,
+    }
+}
+
+/// Total number of points in the segments
+fn count_points(segments: &[&mut DrawingSegment]) -> usize {
+    segments.iter().map(|s| s.point_count()).sum()
+}
+
+/// Simplify oldest segments until total number of points fits into the budget.
+/// The most recent segment is kept intact.
+fn simplify_oldest(segments: &mut [&mut DrawingSegment], max_points: usize) {
+    let mut total = count_points(segments);
+    let older = segments.len().saturating_sub(1);
+    while total > max_points {
+        let mut simplified = false;
+        for segment in segments[..older].iter_mut() {
+            if total <= max_points {
+                break;
+            }
+            let before = segment.point_count();
+            if segment.simplify() {
+                total -= before - segment.point_count();
+                simplified = true;
+            }
+        }
+        if !simplified {
+            // Nothing more to simplify
+            break;
+        }
+    }
+}
+
+/// Segment is either a freehand line or a stamp (emoji or shape) placed at a point
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+enum SegmentKind {
+    #[serde(rename_all = "camelCase")]
+    Line {
+        stroke: String,
+        line_width: i32,
+        points: Vec<Point>,
+    },
+    Stamp {
+        stamp: String,
+        at: Point,
+        size: u32,
+    },
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -257,19 +1756,25 @@ struct Point {
 }
 
 impl Games {
-    pub fn new() -> Self {
+    pub fn new(config: GamesConfig) -> Self {
         Self {
-            pending_ids: HashSet::new(),
+            pending_ids: HashMap::new(),
             rooms: HashMap::new(),
+            removed_players: HashMap::new(),
+            identities: HashMap::new(),
+            audits: HashMap::new(),
+            reports: HashMap::new(),
+            config: Arc::new(config),
         }
     }
 
-    /// Reserve a game id
-    pub fn reserve_id(&mut self) -> String {
-        let mut len = 6;
+    /// Reserve a game id for a game with given settings
+    pub fn reserve_id(&mut self, settings: GameSettings) -> String {
+        let room_codes = &self.config.room_codes;
+        let mut len = room_codes.min_len;
         let id = loop {
             // Generate unique game ID
-            let id = rand_str(len);
+            let id = room_codes.generate(len);
             if !self.exists(&id) {
                 // Unique
                 break id;
@@ -277,10 +1782,14 @@ impl Games {
             len += 1;
         };
 
-        self.pending_ids.insert(id.clone());
+        self.pending_ids.insert(id.clone(), settings);
         id
     }
 
+    pub fn config(&self) -> &GamesConfig {
+        &self.config
+    }
+
     /// Try to find a game by ID
     pub fn find_mut(&mut self, id: &str) -> Option<&mut Game> {
         self.rooms.get_mut(id)
@@ -290,85 +1799,1894 @@ impl Games {
         self.rooms.get(id)
     }
 
+    /// Iterate over all games with joined players
+    pub fn iter(&self) -> impl Iterator<Item = &Game> {
+        self.rooms.values()
+    }
+
     /// Return whether a game or pending game exists
     pub fn exists(&self, game_id: &str) -> bool {
-        self.pending_ids.contains(game_id) || self.rooms.contains_key(game_id)
+        self.pending_ids.contains_key(game_id) || self.rooms.contains_key(game_id)
+    }
+
+    /// Return whether the game has as many players as its settings allow
+    pub fn is_full(&self, game_id: &str) -> bool {
+        self.rooms.get(game_id).is_some_and(|game| {
+            game.settings
+                .max_players
+                .is_some_and(|max| game.players.len() >= max)
+        })
+    }
+
+    /// Return whether the player is in any game
+    pub fn has_player(&self, player_id: &Uuid) -> bool {
+        self.rooms
+            .values()
+            .any(|game| game.players.iter().any(|p| &p.id == player_id))
+    }
+
+    /// Return player id only if that player belongs to the game or was recently removed from it.
+    /// Ids from other games (or from no game at all) should not be resumed.
+    pub fn resume_player_id(&self, game_id: &str, player_id: Option<Uuid>) -> Option<Uuid> {
+        player_id.filter(|id| {
+            let in_game = self
+                .rooms
+                .get(game_id)
+                .is_some_and(|game| game.players.iter().any(|p| &p.id == id));
+            in_game || self.removed_player(game_id, id).is_some()
+        })
+    }
+
+    /// Find a player that was removed from the game recently enough to be restored
+    fn removed_player(&self, game_id: &str, player_id: &Uuid) -> Option<&Player> {
+        self.removed_players
+            .get(&(game_id.to_string(), *player_id))
+            .filter(|(_, removed_at)| removed_at.elapsed() < self.config.restore_removed_within)
+            .map(|(player, _)| player)
     }
 
-    fn new_player(player_id: Uuid, nickname: Option<String>) -> Player {
+    fn new_player(&self, game_id: &str, player_id: Uuid, nickname: Option<String>) -> Player {
+        let restored = self
+            .removed_player(game_id, &player_id)
+            .map(|player| player.nickname.clone());
+        let nickname = nickname
+            .as_deref()
+            .and_then(nicknames::sanitize)
+            .or(restored)
+            .unwrap_or_else(|| {
+                // Generate a nickname that is unique within the game
+                let taken: Vec<&str> = self
+                    .rooms
+                    .get(game_id)
+                    .map(|game| game.players.iter().map(|p| p.nickname.as_str()).collect())
+                    .unwrap_or_default();
+                nicknames::generate(&mut rand::thread_rng(), &taken)
+            });
         Player {
             id: player_id,
-            nickname: nickname.unwrap_or_else(|| rand_str(4).to_lowercase()),
+            nickname,
+            away: false,
+        }
+    }
+
+    /// Nickname last used by the client identity in any game
+    pub fn identity_nickname(&self, client_id: &Uuid) -> Option<String> {
+        self.identities.get(client_id).cloned()
+    }
+
+    /// Remember the nickname of a client identity
+    pub fn remember_identity(&mut self, client_id: Uuid, nickname: String) {
+        if !self.identities.contains_key(&client_id) && self.identities.len() >= MAX_IDENTITIES {
+            // Forget any identity to make room
+            if let Some(evict) = self.identities.keys().next().cloned() {
+                self.identities.remove(&evict);
+            }
         }
+        self.identities.insert(client_id, nickname);
     }
 
-    /// Adds a player to existing game or creates a game
+    /// Adds a player to existing game or creates a game.
+    /// Return None if the game is full. Players already in the game can always rejoin.
     pub fn add_player(
         &mut self,
         game_id: &str,
         player_id: Uuid,
         nickname: Option<String>,
-    ) -> (&Game, Player) {
-        let player = Self::new_player(player_id, nickname);
+    ) -> Option<(&Game, Player)> {
+        let joined = self
+            .rooms
+            .get(game_id)
+            .is_some_and(|game| game.player(&player_id).is_some());
+        if !joined && self.is_full(game_id) {
+            return None;
+        }
+        let player = self.new_player(game_id, player_id, nickname);
+        self.removed_players
+            .remove(&(game_id.to_string(), player_id));
+        let config = Arc::clone(&self.config);
+        let settings = self.pending_ids.get(game_id).cloned().unwrap_or_default();
         let game = self
             .rooms
             .entry(game_id.to_string())
             .and_modify(|game| {
                 game.add_player(player.clone());
             })
-            .or_insert_with(|| Game::new(game_id.to_string(), player.clone()));
-        (game, player)
+            .or_insert_with(|| Game::new(game_id.to_string(), player.clone(), config, settings));
+        Some((game, player))
+    }
+
+    /// Update drawing activity in all games.
+    /// Return games where activity has changed together with the new state.
+    pub fn update_drawer_activity(&mut self, now: Instant) -> Vec<(Game, bool)> {
+        self.rooms
+            .values_mut()
+            .filter_map(|game| {
+                game.drawer_activity(now)
+                    .map(|active| (game.clone(), active))
+            })
+            .collect()
+    }
+
+    /// Expire typing indicators in all games. Return games with players who stopped typing.
+    pub fn expire_typing(&mut self, now: Instant) -> Vec<(Game, Vec<Uuid>)> {
+        self.rooms
+            .values_mut()
+            .filter_map(|game| {
+                let expired = game.expire_typing(now);
+                (!expired.is_empty()).then(|| (game.clone(), expired))
+            })
+            .collect()
+    }
+
+    /// Forfeit rounds where drawing player hasn't drawn anything. Return changed games.
+    pub fn forfeit_idle_drawers(&mut self, now: Instant) -> Vec<Game> {
+        self.rooms
+            .values_mut()
+            .filter_map(|game| game.forfeit_idle_drawer(now).then(|| game.clone()))
+            .collect()
+    }
+
+    /// Advance games that have shown round summary long enough. Return changed games.
+    pub fn advance_round_summaries(&mut self, now: Instant) -> Vec<Game> {
+        self.rooms
+            .values_mut()
+            .filter_map(|game| game.advance_round_summary(now).then(|| game.clone()))
+            .collect()
+    }
+
+    /// Update whether enough guessing players are connected in all games.
+    /// Return games that have changed together with whether the round has ended.
+    pub fn update_guessers_presence(
+        &mut self,
+        now: Instant,
+        is_connected: impl Fn(&Uuid) -> bool,
+    ) -> Vec<(Game, bool)> {
+        self.rooms
+            .values_mut()
+            .filter_map(|game| {
+                let active_player_id = game.active_player_id();
+                let connected = game
+                    .players
+                    .iter()
+                    .filter(|p| p.id != active_player_id && is_connected(&p.id))
+                    .count();
+                let min_guessers = game.settings.min_guessers.unwrap_or(1).max(1);
+                game.update_guessers_presence(connected >= min_guessers, now)
+                    .map(|ended| (game.clone(), ended))
+            })
+            .collect()
     }
 
-    /// Remove player from all games. Return a list of modified games.
-    pub fn remove_player(&mut self, player_id: &Uuid) -> Vec<Game> {
+    /// Remove player from all games. Return a list of modified games together with the removed player.
+    pub fn remove_player(&mut self, player_id: &Uuid) -> Vec<(Game, Player)> {
+        let now = Instant::now();
         let mut empty_games = vec![];
         let mut modified_games = vec![];
 
         {
             // Remove players
             for game in self.rooms.values_mut() {
-                let modified = game.remove_player(player_id);
+                let removed = game.remove_player(player_id);
+                if let Some(player) = &removed {
+                    // Remember the player in case they come back
+                    self.removed_players
+                        .insert((game.id.clone(), player.id), (player.clone(), now));
+                }
                 if game.players.is_empty() {
                     empty_games.push(game.id.clone());
-                } else if modified {
-                    modified_games.push(game.clone());
+                } else if let Some(player) = removed {
+                    modified_games.push((game.clone(), player));
                 }
             }
         }
 
+        // Forget players that can no longer be restored
+        let restore_within = self.config.restore_removed_within;
+        self.removed_players
+            .retain(|_, (_, removed_at)| now.duration_since(*removed_at) < restore_within);
+
         // Remove empty rooms
         for game_id in empty_games {
             log::info!("Removing empty game {}", game_id);
             self.rooms.remove(&game_id);
+            self.audits.remove(&game_id);
+            self.reports.remove(&game_id);
         }
 
         modified_games
     }
-}
-
-/// Generate a random string
-fn rand_str(len: usize) -> String {
-    rand::thread_rng()
-        .sample_iter(Alphanumeric)
-        .take(len)
-        .collect()
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Record an incoming event of the game. Oldest events are evicted once limit is reached.
+    pub fn record_audit(&mut self, game_id: &str, entry: AuditEntry) {
+        let limit = match self.config.audit_limit {
+            Some(limit) => limit,
+            None => return,
+        };
+        if !self.rooms.contains_key(game_id) {
+            return;
+        }
+        let audit = self.audits.entry(game_id.to_string()).or_default();
+        audit.push(entry);
+        if audit.len() > limit {
+            let evict = audit.len() - limit;
+            audit.drain(..evict);
+        }
+    }
+
+    /// Record a report of an abusive player in the game. Oldest reports are evicted once limit is reached.
+    /// Return true once enough players have reported the player to let the host know.
+    pub fn report_player(&mut self, game_id: &str, mut report: Report) -> Option<bool> {
+        let game = self.rooms.get(game_id)?;
+        if report.reporter_id == report.player_id || game.player(&report.player_id).is_none() {
+            return None;
+        }
+        let reports = self.reports.entry(game_id.to_string()).or_default();
+        let already_reported = reports
+            .iter()
+            .any(|r| r.reporter_id == report.reporter_id && r.player_id == report.player_id);
+        let player_id = report.player_id;
+        report.reason = report.reason.chars().take(MAX_REPORT_REASON_LEN).collect();
+        reports.push(report);
+        if reports.len() > MAX_REPORTS {
+            let evict = reports.len() - MAX_REPORTS;
+            reports.drain(..evict);
+        }
+
+        // Notify only when the threshold is crossed by a new reporter
+        let reporters = reports
+            .iter()
+            .filter(|r| r.player_id == player_id)
+            .map(|r| r.reporter_id)
+            .collect::<HashSet<_>>()
+            .len();
+        Some(!already_reported && reporters == self.config.report_threshold)
+    }
+
+    /// Recorded reports of the game from oldest to newest
+    pub fn reports(&self, game_id: &str) -> Option<&[Report]> {
+        self.rooms.get(game_id)?;
+        Some(
+            self.reports
+                .get(game_id)
+                .map_or(&[], |reports| reports.as_slice()),
+        )
+    }
+
+    /// Recorded incoming events of the game from oldest to newest
+    pub fn audit(&self, game_id: &str) -> Option<&[AuditEntry]> {
+        self.rooms.get(game_id)?;
+        Some(
+            self.audits
+                .get(game_id)
+                .map_or(&[], |audit| audit.as_slice()),
+        )
+    }
+}
+
+/// Hide all letters of the word except the first and the last ones
+fn mask_word(word: &str) -> String {
+    let len = word.chars().count();
+    word.chars()
+        .enumerate()
+        .map(|(i, c)| if i == 0 || i + 1 == len { c } else { '*' })
+        .collect()
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            curr.push((prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1));
+        }
+        prev = curr;
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
     #[test]
     fn games_reserve_id() {
-        let mut games = Games::new();
-        assert!(games.reserve_id().len() > 0);
+        let mut games = Games::new(GamesConfig::default());
+        assert!(!games.reserve_id(GameSettings::default()).is_empty());
         assert_eq!(1, games.pending_ids.len(), "pending_ids.len()");
     }
 
+    #[test]
+    fn game_settings_palette() {
+        let settings: GameSettings =
+            serde_json::from_value(serde_json::json!({"palette": "rgb(0, 0, 0), #ffffff,"}))
+                .expect("Comma separated");
+        assert_eq!(vec!["rgb(0, 0, 0)", "#ffffff"], settings.palette);
+
+        let settings: GameSettings =
+            serde_json::from_value(serde_json::json!({"palette": ["rgb(0, 0, 0)", "#ffffff"]}))
+                .expect("List");
+        assert_eq!(vec!["rgb(0, 0, 0)", "#ffffff"], settings.palette);
+    }
+
+    #[test]
+    fn games_add_player_to_full_game() {
+        let mut games = Games::new(GamesConfig::default());
+        let game_id = games.reserve_id(GameSettings {
+            max_players: Some(2),
+            ..GameSettings::default()
+        });
+        let players = [Uuid::new_v4(), Uuid::new_v4()];
+        for player_id in &players {
+            assert!(games.add_player(&game_id, *player_id, None).is_some());
+        }
+
+        assert!(
+            games.add_player(&game_id, Uuid::new_v4(), None).is_none(),
+            "full"
+        );
+        assert_eq!(2, games.find(&game_id).unwrap().players.len());
+        assert!(
+            games.add_player(&game_id, players[1], None).is_some(),
+            "rejoin"
+        );
+    }
+
+    #[test]
+    fn games_reserve_id_config() {
+        let mut games = Games::new(GamesConfig {
+            room_codes: RoomCodeConfig {
+                alphabet: ('0'..='9').collect(),
+                min_len: 4,
+            },
+            ..GamesConfig::default()
+        });
+        let id = games.reserve_id(GameSettings::default());
+        assert_eq!(4, id.len(), "min length");
+        assert!(id.chars().all(|c| c.is_ascii_digit()), "{} is numeric", id);
+
+        let mut games = Games::new(GamesConfig {
+            room_codes: RoomCodeConfig {
+                min_len: 10,
+                ..RoomCodeConfig::default()
+            },
+            ..GamesConfig::default()
+        });
+        assert_eq!(10, games.reserve_id(GameSettings::default()).len());
+    }
+
+    #[test]
+    fn games_reserve_id_grows_on_collision() {
+        let mut games = Games::new(GamesConfig {
+            room_codes: RoomCodeConfig {
+                alphabet: vec!['a'],
+                min_len: 1,
+            },
+            ..GamesConfig::default()
+        });
+        assert_eq!("a", games.reserve_id(GameSettings::default()));
+        assert_eq!("aa", games.reserve_id(GameSettings::default()));
+    }
+
+    #[test]
+    fn games_foreign_player_is_not_resumed() {
+        let mut games = Games::new(GamesConfig::default());
+        let player_id = Uuid::new_v4();
+        let player_id_2 = Uuid::new_v4();
+        games.add_player("first", player_id, None);
+        games.add_player("second", player_id_2, None);
+
+        assert_eq!(
+            Some(player_id),
+            games.resume_player_id("first", Some(player_id)),
+            "member is resumed"
+        );
+        assert_eq!(
+            None,
+            games.resume_player_id("second", Some(player_id)),
+            "foreign player is not resumed"
+        );
+        assert_eq!(
+            None,
+            games.resume_player_id("missing", Some(player_id)),
+            "unknown game"
+        );
+    }
+
+    #[test]
+    fn game_stamps() {
+        let mut games = Games::new(GamesConfig::default());
+        let player_id = Uuid::new_v4();
+        let player_id_2 = Uuid::new_v4();
+        games.add_player("test", player_id, None);
+        games.add_player("test", player_id_2, None);
+        let game = games.find_mut("test").unwrap();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+        assert_eq!(
+            SubmitResult::Accepted,
+            game.submit_word(&player_id, "Star".to_string(), canvas)
+        );
+
+        let json = r#"{"id":"s1","stamp":"⭐","at":{"x":10,"y":20},"size":32}"#;
+        let mut segment: DrawingSegment = serde_json::from_str(json).expect("Parse stamp");
+        match &segment.kind {
+            SegmentKind::Stamp { stamp, at, size } => {
+                assert_eq!("⭐", stamp, "stamp");
+                assert_eq!((10, 20), (at.x, at.y), "stamp position");
+                assert_eq!(32, *size, "stamp size");
+            }
+            _ => panic!("Expected stamp segment"),
+        };
+
+        assert!(
+            game.add_segment(&player_id_2, &mut segment.clone())
+                .is_none(),
+            "only drawer places stamps"
+        );
+        assert_eq!(
+            Some(AddedSegment::Stored),
+            game.add_segment(&player_id, &mut segment),
+            "drawer places stamp"
+        );
+
+        let disallowed = r#"{"id":"s2","stamp":"💩","at":{"x":10,"y":20},"size":32}"#;
+        let mut disallowed: DrawingSegment = serde_json::from_str(disallowed).expect("Parse stamp");
+        assert!(
+            game.add_segment(&player_id, &mut disallowed).is_none(),
+            "stamp not allowed"
+        );
+
+        let mut stored = vec![];
+        game.iter_drawing(|s| stored.push(serde_json::to_value(s).expect("Serialize")));
+        assert_eq!(1, stored.len(), "stored segments");
+        let mut expected = serde_json::from_str::<serde_json::Value>(json).unwrap();
+        expected["order"] = 1.into();
+        assert_eq!(expected, stored[0], "stamp round-trip");
+    }
+
+    #[test]
+    fn game_allowed_tools() {
+        let mut games = Games::new(GamesConfig::default());
+        let game_id = games.reserve_id(GameSettings {
+            allowed_tools: vec![Tool::Pen],
+            ..GameSettings::default()
+        });
+        let player_id = Uuid::new_v4();
+        games.add_player(&game_id, player_id, None);
+        let game = games.find_mut(&game_id).unwrap();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+        game.submit_word(&player_id, "Star".to_string(), canvas);
+        let parse = |json| serde_json::from_str::<DrawingSegment>(json).expect("Parse segment");
+
+        let stamp = r#"{"id":"s1","stamp":"⭐","at":{"x":10,"y":20},"size":32}"#;
+        assert_eq!(
+            None,
+            game.add_segment(&player_id, &mut parse(stamp)),
+            "stamp"
+        );
+        let line = r#"{"id":"l1","stroke":"red","lineWidth":2,"points":[{"x":0,"y":0}]}"#;
+        assert_eq!(
+            Some(AddedSegment::Stored),
+            game.add_segment(&player_id, &mut parse(line)),
+            "pen"
+        );
+    }
+
+    #[test]
+    fn game_drop_uncommitted_merged_segment() {
+        let mut games = Games::new(GamesConfig {
+            merge_segments_within: Some(Duration::from_secs(60)),
+            drop_uncommitted_segments: true,
+            ..GamesConfig::default()
+        });
+        let player_id = Uuid::new_v4();
+        games.add_player("test", player_id, None);
+        let game = games.find_mut("test").unwrap();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+        game.submit_word(&player_id, "Line".to_string(), canvas);
+        let parse = |json| serde_json::from_str::<DrawingSegment>(json).expect("Parse segment");
+
+        let first = r#"{"id":"l1","stroke":"red","lineWidth":2,"points":[{"x":0,"y":0}]}"#;
+        let second = r#"{"id":"l2","stroke":"red","lineWidth":2,"points":[{"x":1,"y":1}]}"#;
+        game.add_segment(&player_id, &mut parse(first));
+        game.end_segment(&player_id, "l1");
+        assert_eq!(
+            Some(AddedSegment::Stored),
+            game.add_segment(&player_id, &mut parse(second)),
+            "finished stroke is not extended"
+        );
+
+        assert_eq!(
+            Some("l2".to_string()),
+            game.drop_uncommitted_segment(&player_id)
+        );
+        let changes = game.drawing_changes(0).expect("Drawing");
+        let ids: Vec<&str> = changes.segments.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(vec!["l1"], ids, "finished stroke kept");
+    }
+
+    #[test]
+    fn game_segment_ids() {
+        let mut games = Games::new(GamesConfig::default());
+        let player_id = Uuid::new_v4();
+        games.add_player("test", player_id, None);
+        let game = games.find_mut("test").unwrap();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+        game.submit_word(&player_id, "Line".to_string(), canvas);
+        let parse = |json| serde_json::from_str::<DrawingSegment>(json).expect("Parse segment");
+
+        let empty = r#"{"id":"","stroke":"red","lineWidth":2,"points":[{"x":0,"y":0}]}"#;
+        assert_eq!(
+            None,
+            game.add_segment(&player_id, &mut parse(empty)),
+            "empty id"
+        );
+
+        let line = r#"{"id":"l1","stroke":"red","lineWidth":2,"points":[{"x":0,"y":0}]}"#;
+        assert_eq!(
+            Some(AddedSegment::Stored),
+            game.add_segment(&player_id, &mut parse(line))
+        );
+        assert_eq!(
+            None,
+            game.add_segment(&player_id, &mut parse(line)),
+            "duplicate live id"
+        );
+        game.remove_segment("l1");
+        assert_eq!(
+            None,
+            game.add_segment(&player_id, &mut parse(line)),
+            "reuse after remove"
+        );
+    }
+
+    #[test]
+    fn game_merge_segments() {
+        let mut games = Games::new(GamesConfig {
+            merge_segments_within: Some(Duration::from_secs(1)),
+            ..GamesConfig::default()
+        });
+        let player_id = Uuid::new_v4();
+        games.add_player("test", player_id, None);
+        let game = games.find_mut("test").unwrap();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+        assert_eq!(
+            SubmitResult::Accepted,
+            game.submit_word(&player_id, "Line".to_string(), canvas)
+        );
+
+        let first = r#"{"id":"l1","stroke":"red","lineWidth":2,"points":[{"x":0,"y":0}]}"#;
+        let second = r#"{"id":"l2","stroke":"red","lineWidth":2,"points":[{"x":1,"y":1}]}"#;
+        let other = r#"{"id":"l3","stroke":"blue","lineWidth":2,"points":[{"x":2,"y":2}]}"#;
+        let parse = |json| serde_json::from_str::<DrawingSegment>(json).expect("Parse segment");
+
+        assert_eq!(
+            Some(AddedSegment::Stored),
+            game.add_segment(&player_id, &mut parse(first))
+        );
+        assert_eq!(
+            Some(AddedSegment::Merged {
+                into_segment_id: "l1".to_string()
+            }),
+            game.add_segment(&player_id, &mut parse(second))
+        );
+        assert_eq!(
+            Some(AddedSegment::Stored),
+            game.add_segment(&player_id, &mut parse(other)),
+            "different stroke is not merged"
+        );
+
+        let mut stored = vec![];
+        game.iter_drawing(|s| stored.push(s.clone()));
+        assert_eq!(2, stored.len(), "stored segments");
+        match &stored[0].kind {
+            SegmentKind::Line { points, .. } => assert_eq!(2, points.len(), "merged points"),
+            _ => panic!("Expected line segment"),
+        };
+        assert_eq!(
+            None,
+            game.add_segment(&player_id, &mut parse(second)),
+            "merged id is taken"
+        );
+
+        game.remove_segment("l2");
+        let mut count = 0;
+        game.iter_drawing(|_| count += 1);
+        assert_eq!(1, count, "merged segment removed by its id");
+    }
+
+    #[test]
+    fn game_drawing_svg() {
+        let mut games = Games::new(GamesConfig::default());
+        let player_id = Uuid::new_v4();
+        games.add_player("test", player_id, None);
+        let game = games.find_mut("test").unwrap();
+        assert!(game.drawing_svg().is_none(), "no drawing while choosing");
+
+        let canvas = CanvasSize {
+            width: 100,
+            height: 50,
+        };
+        assert_eq!(
+            SubmitResult::Accepted,
+            game.submit_word(&player_id, "Line".to_string(), canvas)
+        );
+        let segment = r##"{"id":"l1","stroke":"#ff0000","lineWidth":3,"points":[{"x":0,"y":0},{"x":10,"y":20}]}"##;
+        let mut segment = serde_json::from_str(segment).expect("Parse segment");
+        assert!(game.add_segment(&player_id, &mut segment).is_some());
+
+        let svg = game.drawing_svg().expect("Svg");
+        assert!(svg.starts_with("<svg "), "svg root: {}", svg);
+        assert!(
+            svg.contains(r#"viewBox="0 0 100 50""#),
+            "canvas size: {}",
+            svg
+        );
+        assert!(
+            svg.contains(
+                r##"<polyline points="0,0 10,20" fill="none" stroke="#ff0000" stroke-width="3""##
+            ),
+            "polyline: {}",
+            svg
+        );
+        assert!(svg.ends_with("</svg>"), "svg end: {}", svg);
+    }
+
+    #[test]
+    fn game_seed_is_stable() {
+        let mut games = Games::new(GamesConfig::default());
+        let player_id = Uuid::new_v4();
+        let (game, _) = games.add_player("test", player_id, None).expect("Join");
+        let first = serde_json::to_value(game).expect("Serialize");
+        assert!(
+            first["seed"].as_u64().expect("Seed") < 1 << 53,
+            "fits JS number"
+        );
+
+        // Reconnect and serialize again
+        let (game, _) = games.add_player("test", player_id, None).expect("Join");
+        let second = serde_json::to_value(game.clone()).expect("Serialize");
+        assert_eq!(first["seed"], second["seed"], "seed");
+    }
+
+    #[test]
+    fn game_remove_player_picks_next() {
+        let mut games = Games::new(GamesConfig::default());
+        let ids: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+        for id in &ids {
+            games.add_player("test", *id, None);
+        }
+        let game = games.find_mut("test").unwrap();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+        assert_eq!(
+            SubmitResult::Accepted,
+            game.submit_word(&ids[0], "word".to_string(), canvas)
+        );
+        assert_eq!(GuessResult::Correct, game.guess_word(&ids[1], "word"));
+
+        game.remove_player(&ids[2]);
+        assert_eq!(ids[1], game.stage.player_id(), "inactive player removed");
+
+        game.add_player(Player {
+            id: ids[2],
+            nickname: "back".to_string(),
+            away: false,
+        });
+        game.remove_player(&ids[1]);
+        assert_eq!(ids[2], game.stage.player_id(), "next player in order");
+
+        game.remove_player(&ids[2]);
+        assert_eq!(ids[0], game.stage.player_id(), "wraps around");
+    }
+
+    #[test]
+    fn game_drawer_activity() {
+        let mut games = Games::new(GamesConfig::default());
+        let player_id = Uuid::new_v4();
+        games.add_player("test", player_id, None);
+        let game = games.find_mut("test").unwrap();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+        assert_eq!(
+            SubmitResult::Accepted,
+            game.submit_word(&player_id, "Line".to_string(), canvas)
+        );
+        assert_eq!(None, game.drawer_activity(Instant::now()), "not drawn yet");
+
+        let segment = r#"{"id":"l1","stroke":"red","lineWidth":2,"points":[{"x":0,"y":0}]}"#;
+        let mut segment = serde_json::from_str(segment).expect("Parse segment");
+        assert!(game.add_segment(&player_id, &mut segment).is_some());
+        let drawn_at = Instant::now();
+
+        assert_eq!(Some(true), game.drawer_activity(drawn_at), "active");
+        assert_eq!(None, game.drawer_activity(drawn_at), "no repeated changes");
+        assert_eq!(
+            None,
+            game.drawer_activity(drawn_at + Duration::from_secs(1)),
+            "short pause"
+        );
+        assert_eq!(
+            Some(false),
+            game.drawer_activity(drawn_at + DRAWER_IDLE_AFTER * 2),
+            "idle after gap"
+        );
+        assert_eq!(
+            None,
+            game.drawer_activity(drawn_at + DRAWER_IDLE_AFTER * 3),
+            "stays idle"
+        );
+    }
+
+    #[test]
+    fn scoring_policies() {
+        let drawing_player_id = Uuid::new_v4();
+        let guessing_player_id = Uuid::new_v4();
+        let round = Round {
+            drawing_player_id,
+            guessing_player_id,
+            time_taken: Duration::from_secs(40),
+        };
+
+        let flat = ScoringPolicy::Flat.award(&round);
+        assert_eq!(1, flat.len(), "flat awards");
+        assert_eq!(Some(&1), flat.get(&guessing_player_id), "flat guesser");
+
+        let time_based = ScoringPolicy::TimeBased.award(&round);
+        assert_eq!(2, time_based.len(), "time based awards");
+        assert_eq!(
+            Some(&8),
+            time_based.get(&guessing_player_id),
+            "time guesser"
+        );
+        assert_eq!(Some(&4), time_based.get(&drawing_player_id), "time drawer");
+
+        let slow = ScoringPolicy::TimeBased.award(&Round {
+            time_taken: Duration::from_secs(60 * 60),
+            ..round
+        });
+        assert_eq!(Some(&1), slow.get(&guessing_player_id), "min points");
+    }
+
+    #[test]
+    fn game_scoring_setting() {
+        let mut games = Games::new(GamesConfig::default());
+        let game_id = games.reserve_id(GameSettings {
+            scoring: ScoringPolicy::TimeBased,
+            ..GameSettings::default()
+        });
+        let player_id = Uuid::new_v4();
+        let player_id_2 = Uuid::new_v4();
+        games.add_player(&game_id, player_id, None);
+        games.add_player(&game_id, player_id_2, None);
+        let game = games.find_mut(&game_id).unwrap();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+        assert_eq!(
+            SubmitResult::Accepted,
+            game.submit_word(&player_id, "word".to_string(), canvas)
+        );
+        assert_eq!(GuessResult::Correct, game.guess_word(&player_id_2, "word"));
+        assert_eq!(Some(&10), game.scores.get(&player_id_2), "guesser");
+        assert_eq!(Some(&5), game.scores.get(&player_id), "drawer");
+    }
+
+    #[test]
+    fn game_history_limit() {
+        let mut games = Games::new(GamesConfig {
+            history_limit: 3,
+            ..GamesConfig::default()
+        });
+        let player_id = Uuid::new_v4();
+        let player_id_2 = Uuid::new_v4();
+        games.add_player("test", player_id, None);
+        games.add_player("test", player_id_2, None);
+        let game = games.find_mut("test").unwrap();
+
+        let mut drawing = player_id;
+        let mut guessing = player_id_2;
+        for i in 0..5 {
+            let word = format!("word{}", i);
+            let canvas = CanvasSize {
+                width: 100,
+                height: 100,
+            };
+            assert_eq!(
+                SubmitResult::Accepted,
+                game.submit_word(&drawing, word.clone(), canvas)
+            );
+            assert_eq!(GuessResult::Correct, game.guess_word(&guessing, &word));
+            std::mem::swap(&mut drawing, &mut guessing);
+        }
+
+        assert_eq!(3, game.history.len(), "history len");
+        assert_eq!("word2", game.history[0].word, "oldest kept turn");
+        assert_eq!(Some(&2), game.scores.get(&player_id), "first player score");
+        assert_eq!(
+            Some(&3),
+            game.scores.get(&player_id_2),
+            "second player score"
+        );
+    }
+
+    #[test]
+    fn game_word_tip_phrase() {
+        let mut games = Games::new(GamesConfig::default());
+        let player_id = Uuid::new_v4();
+        games.add_player("test", player_id, None);
+        let game = games.find_mut("test").unwrap();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+
+        assert_eq!(
+            SubmitResult::Accepted,
+            game.submit_word(&player_id, " Ice cream cône ".to_string(), canvas)
+        );
+        assert_eq!(
+            Some("i*e c***m c**e".to_string()),
+            game.ask_word_tip(),
+            "tip"
+        );
+    }
+
+    #[test]
+    fn game_word_too_long() {
+        let mut games = Games::new(GamesConfig::default());
+        let player_id = Uuid::new_v4();
+        games.add_player("test", player_id, None);
+        let game = games.find_mut("test").unwrap();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+
+        let res = game.submit_word(&player_id, "a".repeat(MAX_WORD_LEN + 1), canvas.clone());
+        assert_eq!(SubmitResult::TooLong, res, "too long word is rejected");
+        assert!(game.ask_word_tip().is_none(), "still choosing");
+
+        let res = game.submit_word(&player_id, "ä".repeat(MAX_WORD_LEN), canvas);
+        assert_eq!(
+            SubmitResult::Accepted,
+            res,
+            "max length is counted in characters"
+        );
+    }
+
+    #[test]
+    fn game_submit_and_guess_results() {
+        let mut games = Games::new(GamesConfig::default());
+        let player_id = Uuid::new_v4();
+        let player_id_2 = Uuid::new_v4();
+        games.add_player("test", player_id, None);
+        games.add_player("test", player_id_2, None);
+        let game = games.find_mut("test").unwrap();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+
+        assert_eq!(
+            GuessResult::WrongStage,
+            game.guess_word(&player_id_2, "word"),
+            "guess while choosing"
+        );
+        assert_eq!(
+            SubmitResult::NotYourTurn,
+            game.submit_word(&player_id_2, "word".to_string(), canvas.clone())
+        );
+        assert_eq!(
+            SubmitResult::TooLong,
+            game.submit_word(&player_id, "w".repeat(MAX_WORD_LEN + 1), canvas.clone())
+        );
+        assert_eq!(
+            SubmitResult::Accepted,
+            game.submit_word(&player_id, "word".to_string(), canvas.clone())
+        );
+        assert_eq!(
+            SubmitResult::WrongStage,
+            game.submit_word(&player_id, "word".to_string(), canvas)
+        );
+        assert_eq!(
+            GuessResult::NotYourTurn,
+            game.guess_word(&player_id, "word"),
+            "drawer guesses"
+        );
+        assert_eq!(GuessResult::Wrong, game.guess_word(&player_id_2, "bird"));
+        assert_eq!(GuessResult::Correct, game.guess_word(&player_id_2, "WORD"));
+    }
+
+    #[test]
+    fn game_max_guesses() {
+        let mut games = Games::new(GamesConfig::default());
+        let settings = GameSettings {
+            max_guesses: Some(2),
+            ..GameSettings::default()
+        };
+        let game_id = games.reserve_id(settings);
+        let player_1 = Uuid::new_v4();
+        let player_2 = Uuid::new_v4();
+        let player_3 = Uuid::new_v4();
+        games.add_player(&game_id, player_1, None);
+        games.add_player(&game_id, player_2, None);
+        games.add_player(&game_id, player_3, None);
+        let game = games.find_mut(&game_id).unwrap();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+
+        game.submit_word(&player_1, "word".to_string(), canvas.clone());
+        assert_eq!(GuessResult::Wrong, game.guess_word(&player_2, "bird"));
+        assert_eq!(GuessResult::Wrong, game.guess_word(&player_2, "card"));
+        assert_eq!(
+            GuessResult::OutOfGuesses,
+            game.guess_word(&player_2, "word"),
+            "cap reached"
+        );
+        assert_eq!(GuessResult::Correct, game.guess_word(&player_3, "word"));
+
+        // Next round
+        game.submit_word(&player_3, "cat".to_string(), canvas);
+        assert_eq!(
+            GuessResult::Correct,
+            game.guess_word(&player_2, "cat"),
+            "reset"
+        );
+    }
+
+    #[test]
+    fn game_repeated_guess() {
+        let mut games = Games::new(GamesConfig::default());
+        let game_id = games.reserve_id(GameSettings {
+            max_guesses: Some(2),
+            ..GameSettings::default()
+        });
+        let player_1 = Uuid::new_v4();
+        let player_2 = Uuid::new_v4();
+        games.add_player(&game_id, player_1, None);
+        games.add_player(&game_id, player_2, None);
+        let game = games.find_mut(&game_id).unwrap();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+
+        game.submit_word(&player_1, "word".to_string(), canvas);
+        assert_eq!(GuessResult::Wrong, game.guess_word(&player_2, "bird"));
+        assert_eq!(GuessResult::Repeated, game.guess_word(&player_2, "bird"));
+        assert_eq!(GuessResult::Repeated, game.guess_word(&player_2, "BIRD"));
+        assert_eq!(GuessResult::Wrong, game.guess_word(&player_2, "card"));
+        assert_eq!(
+            GuessResult::OutOfGuesses,
+            game.guess_word(&player_2, "word"),
+            "repeated guesses not counted"
+        );
+    }
+
+    #[test]
+    fn game_guess_progress() {
+        let mut games = Games::new(GamesConfig::default());
+        let settings = GameSettings {
+            guess_progress: true,
+            ..GameSettings::default()
+        };
+        let game_id = games.reserve_id(settings);
+        let player_1 = Uuid::new_v4();
+        let player_2 = Uuid::new_v4();
+        let player_3 = Uuid::new_v4();
+        games.add_player(&game_id, player_1, None);
+        games.add_player(&game_id, player_2, None);
+        games.add_player(&game_id, player_3, None);
+        let game = games.find_mut(&game_id).unwrap();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+
+        assert_eq!(None, game.guess_progress(), "not drawing");
+        game.submit_word(&player_1, "Giraffe".to_string(), canvas);
+        game.guess_word(&player_2, "elephant");
+        assert_eq!(
+            Some(GuessProgress {
+                close_count: 0,
+                total_guessers: 2
+            }),
+            game.guess_progress(),
+            "far guess"
+        );
+        game.guess_word(&player_2, "girafe");
+        game.guess_word(&player_2, "giraf");
+        assert_eq!(
+            Some(GuessProgress {
+                close_count: 1,
+                total_guessers: 2
+            }),
+            game.guess_progress(),
+            "close guess"
+        );
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(0, edit_distance("cat", "cat"));
+        assert_eq!(1, edit_distance("cat", "cut"));
+        assert_eq!(1, edit_distance("cat", "cats"));
+        assert_eq!(3, edit_distance("", "cat"));
+        assert_eq!(3, edit_distance("kitten", "sitting"));
+    }
+
+    #[test]
+    fn game_turn_timeline() {
+        let mut games = Games::new(GamesConfig::default());
+        let player_1 = Uuid::new_v4();
+        let player_2 = Uuid::new_v4();
+        games.add_player("test", player_1, None);
+        games.add_player("test", player_2, None);
+        let game = games.find_mut("test").unwrap();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+        let segment = |id: &str| {
+            let json = format!(
+                r#"{{"id":"{}","stroke":"red","lineWidth":2,"points":[{{"x":0,"y":0}}]}}"#,
+                id
+            );
+            serde_json::from_str::<DrawingSegment>(&json).expect("Parse segment")
+        };
+
+        game.submit_word(&player_1, "word".to_string(), canvas);
+        game.add_segment(&player_1, &mut segment("a"));
+        game.add_segment(&player_1, &mut segment("b"));
+        game.add_segment(&player_1, &mut segment("c"));
+        game.remove_segment("b");
+        game.guess_word(&player_2, "word");
+
+        let timeline = game.turn_timeline(0).expect("Timeline");
+        let ids: Vec<&str> = timeline.iter().map(|t| t.segment.id.as_str()).collect();
+        assert_eq!(vec!["a", "c"], ids);
+        assert!(timeline[0].offset <= timeline[1].offset, "ordered");
+        assert!(game.turn_timeline(1).is_none(), "no such turn");
+    }
+
+    #[test]
+    fn game_drawer_disconnect() {
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+        let player_1 = Uuid::new_v4();
+        let player_2 = Uuid::new_v4();
+        let new_game = |drawer_disconnect| {
+            let mut games = Games::new(GamesConfig::default());
+            let game_id = games.reserve_id(GameSettings {
+                drawer_disconnect,
+                ..GameSettings::default()
+            });
+            games.add_player(&game_id, player_1, None);
+            games.add_player(&game_id, player_2, None);
+            let mut game = games.find(&game_id).unwrap().clone();
+            game.submit_word(&player_1, "word".to_string(), canvas.clone());
+            game
+        };
+
+        let mut game = new_game(DrawerDisconnect::Wait);
+        assert!(!game.drawer_disconnected(&player_2), "not drawing");
+        assert!(!game.drawer_disconnected(&player_1), "wait");
+        assert!(game.drawing_svg().is_some(), "still drawing");
+
+        let mut game = new_game(DrawerDisconnect::Forfeit);
+        assert!(game.drawer_disconnected(&player_1), "forfeit");
+        assert_eq!(player_2, game.active_player_id(), "next player");
+        assert_eq!("word", game.history[0].word, "word revealed");
+        assert!(game.history[0].player_guessed.is_none(), "nobody guessed");
+    }
+
+    #[test]
+    fn game_drawing_changes() {
+        let mut games = Games::new(GamesConfig {
+            merge_segments_within: Some(Duration::from_secs(60)),
+            ..GamesConfig::default()
+        });
+        let player_id = Uuid::new_v4();
+        games.add_player("test", player_id, None);
+        let game = games.find_mut("test").unwrap();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+        let segment = |id: &str, stroke: &str| {
+            let json = format!(
+                r#"{{"id":"{}","stroke":"{}","lineWidth":2,"points":[{{"x":0,"y":0}}]}}"#,
+                id, stroke
+            );
+            serde_json::from_str::<DrawingSegment>(&json).expect("Parse segment")
+        };
+        let ids = |segments: &[DrawingSegment]| -> Vec<String> {
+            segments.iter().map(|s| s.id.clone()).collect()
+        };
+
+        assert!(game.drawing_changes(0).is_none(), "no drawing");
+        game.submit_word(&player_id, "word".to_string(), canvas);
+        game.add_segment(&player_id, &mut segment("a", "red"));
+        game.add_segment(&player_id, &mut segment("b", "blue"));
+        let known = game.segment_order;
+        game.add_segment(&player_id, &mut segment("c", "blue"));
+        game.remove_segment("a");
+        game.add_segment(&player_id, &mut segment("d", "red"));
+
+        let changes = game.drawing_changes(known).expect("Changes");
+        assert!(!changes.clear, "known drawing");
+        assert_eq!(vec!["a", "b"], changes.removed_ids, "removed and merged");
+        assert_eq!(vec!["b", "d"], ids(&changes.segments), "newer segments");
+
+        let changes = game.drawing_changes(0).expect("Changes");
+        assert!(changes.clear, "unknown drawing");
+        assert_eq!(vec!["b", "d"], ids(&changes.segments), "all segments");
+    }
+
+    #[test]
+    fn game_ends_below_min_guessers() {
+        let grace = Duration::from_secs(10);
+        let mut games = Games::new(GamesConfig {
+            end_round_without_guessers_after: Some(grace),
+            ..GamesConfig::default()
+        });
+        let game_id = games.reserve_id(GameSettings {
+            min_guessers: Some(2),
+            ..GameSettings::default()
+        });
+        let players = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+        for player_id in &players {
+            games.add_player(&game_id, *player_id, None);
+        }
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+        let game = games.find_mut(&game_id).unwrap();
+        game.submit_word(&players[0], "word".to_string(), canvas);
+        let now = Instant::now();
+
+        assert!(
+            games.update_guessers_presence(now, |_| true).is_empty(),
+            "enough guessers"
+        );
+        // One guessing player disconnects
+        let connected = |id: &Uuid| *id != players[2];
+        assert!(
+            games.update_guessers_presence(now, connected).is_empty(),
+            "grace started"
+        );
+        let changed = games.update_guessers_presence(now + grace, connected);
+        assert_eq!(1, changed.len());
+        assert!(changed[0].1, "round aborted");
+        let game = games.find(&game_id).unwrap();
+        assert!(matches!(game.stage, GameStage::WaitingForPlayers { .. }));
+        assert_eq!("word", game.history[0].word, "word revealed");
+        assert!(game.scores.values().all(|points| *points == 0), "no points");
+    }
+
+    #[test]
+    fn game_ends_below_min_guessers_without_grace() {
+        let mut games = Games::new(GamesConfig::default());
+        assert!(games.config.end_round_without_guessers_after.is_none());
+        let game_id = games.reserve_id(GameSettings {
+            min_guessers: Some(2),
+            ..GameSettings::default()
+        });
+        let players = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+        for player_id in &players {
+            games.add_player(&game_id, *player_id, None);
+        }
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+        let game = games.find_mut(&game_id).unwrap();
+        game.submit_word(&players[0], "word".to_string(), canvas);
+        let now = Instant::now();
+
+        assert!(
+            games.update_guessers_presence(now, |_| true).is_empty(),
+            "enough guessers"
+        );
+        let connected = |id: &Uuid| *id != players[2];
+        let changed = games.update_guessers_presence(now, connected);
+        assert_eq!(1, changed.len());
+        assert!(changed[0].1, "round aborted");
+        let game = games.find(&game_id).unwrap();
+        assert!(matches!(game.stage, GameStage::WaitingForPlayers { .. }));
+
+        let changed = games.update_guessers_presence(now, |_| true);
+        assert_eq!(1, changed.len());
+        assert!(!changed[0].1, "game resumed");
+    }
+
+    #[test]
+    fn game_ends_without_guessers() {
+        let grace = Duration::from_secs(10);
+        let mut games = Games::new(GamesConfig {
+            end_round_without_guessers_after: Some(grace),
+            ..GamesConfig::default()
+        });
+        let player_1 = Uuid::new_v4();
+        let player_2 = Uuid::new_v4();
+        games.add_player("test", player_1, None);
+        games.add_player("test", player_2, None);
+        let game = games.find_mut("test").unwrap();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+        game.submit_word(&player_1, "word".to_string(), canvas);
+        let now = Instant::now();
+
+        assert_eq!(
+            None,
+            game.update_guessers_presence(false, now),
+            "grace started"
+        );
+        assert_eq!(
+            None,
+            game.update_guessers_presence(true, now + grace),
+            "guesser is back"
+        );
+        assert_eq!(
+            None,
+            game.update_guessers_presence(false, now + grace),
+            "grace restarted"
+        );
+        assert_eq!(
+            Some(true),
+            game.update_guessers_presence(false, now + grace * 2),
+            "round ended"
+        );
+        assert!(matches!(
+            game.stage,
+            GameStage::WaitingForPlayers { player_id } if player_id == player_1
+        ));
+        assert_eq!("word", game.history[0].word, "word revealed");
+
+        assert_eq!(
+            None,
+            game.update_guessers_presence(false, now + grace * 3),
+            "still waiting"
+        );
+        assert_eq!(
+            Some(false),
+            game.update_guessers_presence(true, now + grace * 3),
+            "resumed"
+        );
+        assert!(matches!(
+            game.stage,
+            GameStage::PlayerChoosing { player_id } if player_id == player_1
+        ));
+    }
+
+    #[test]
+    fn game_strict_match() {
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+        let player_1 = Uuid::new_v4();
+        let player_2 = Uuid::new_v4();
+        let new_game = |strict_match| {
+            let mut games = Games::new(GamesConfig::default());
+            let game_id = games.reserve_id(GameSettings {
+                strict_match,
+                ..GameSettings::default()
+            });
+            games.add_player(&game_id, player_1, None);
+            games.add_player(&game_id, player_2, None);
+            let mut game = games.find(&game_id).unwrap().clone();
+            game.submit_word(&player_1, "Paris".to_string(), canvas.clone());
+            game
+        };
+
+        let mut game = new_game(false);
+        assert_eq!(GuessResult::Correct, game.guess_word(&player_2, "paris"));
+
+        let mut game = new_game(true);
+        assert_eq!(GuessResult::Wrong, game.guess_word(&player_2, "paris"));
+        assert_eq!(GuessResult::Correct, game.guess_word(&player_2, " Paris "));
+    }
+
+    #[test]
+    fn game_no_drawing_after_round() {
+        let player_1 = Uuid::new_v4();
+        let player_2 = Uuid::new_v4();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+        let segment = || {
+            let json = r#"{"id":"a","stroke":"red","lineWidth":2,"points":[{"x":0,"y":0}]}"#;
+            serde_json::from_str::<DrawingSegment>(json).expect("Parse segment")
+        };
+        let drawing_game = || {
+            let mut games = Games::new(GamesConfig::default());
+            let game_id = games.reserve_id(GameSettings {
+                drawer_disconnect: DrawerDisconnect::Forfeit,
+                ..GameSettings::default()
+            });
+            games.add_player(&game_id, player_1, None);
+            games.add_player(&game_id, player_2, None);
+            let mut game = games.find(&game_id).unwrap().clone();
+            game.submit_word(&player_1, "word".to_string(), canvas.clone());
+            game.add_segment(&player_1, &mut segment());
+            game
+        };
+        let segments_count = |game: &Game| {
+            let mut count = 0;
+            game.iter_drawing(|_| count += 1);
+            count
+        };
+
+        let mut game = drawing_game();
+        game.guess_word(&player_2, "word");
+        assert_eq!(0, segments_count(&game), "guessed");
+        assert!(game.drawing_changes(0).is_none(), "guessed");
+
+        let mut game = drawing_game();
+        game.give_up(&player_2);
+        assert_eq!(0, segments_count(&game), "gave up");
+        assert!(game.drawing_changes(0).is_none(), "gave up");
+
+        let mut game = drawing_game();
+        game.drawer_disconnected(&player_1);
+        assert_eq!(0, segments_count(&game), "drawer disconnected");
+        assert!(game.drawing_changes(0).is_none(), "drawer disconnected");
+
+        let mut game = drawing_game();
+        game.remove_player(&player_1);
+        assert_eq!(0, segments_count(&game), "drawer removed");
+        assert!(game.drawing_changes(0).is_none(), "drawer removed");
+
+        // New word starts an empty drawing
+        game.submit_word(&player_2, "other".to_string(), canvas.clone());
+        assert_eq!(0, segments_count(&game), "new word");
+    }
+
+    #[test]
+    fn game_forfeit_idle_drawer() {
+        let mut games = Games::new(GamesConfig::default());
+        let game_id = games.reserve_id(GameSettings {
+            forfeit_idle_drawer_secs: Some(20),
+            idle_drawer_penalty: 1,
+            ..GameSettings::default()
+        });
+        let player_1 = Uuid::new_v4();
+        let player_2 = Uuid::new_v4();
+        games.add_player(&game_id, player_1, None);
+        games.add_player(&game_id, player_2, None);
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+        let game = games.find_mut(&game_id).unwrap();
+        game.scores.insert(player_1, 3);
+        let started_at = Instant::now();
+        game.submit_word(&player_1, "word".to_string(), canvas);
+
+        assert!(!game.forfeit_idle_drawer(started_at + Duration::from_secs(10)));
+        assert!(game.forfeit_idle_drawer(started_at + Duration::from_secs(21)));
+        assert!(matches!(
+            game.stage,
+            GameStage::PlayerChoosing { player_id } if player_id == player_2
+        ));
+        assert_eq!(Some(&2), game.scores.get(&player_1), "penalty");
+        assert_eq!(Some(false), game.last_turn_outcome().map(|o| o.guessed));
+    }
+
+    #[test]
+    fn game_force_next_round() {
+        let mut games = Games::new(GamesConfig::default());
+        let host = Uuid::new_v4();
+        let player_2 = Uuid::new_v4();
+        let player_3 = Uuid::new_v4();
+        games.add_player("test", host, None);
+        games.add_player("test", player_2, None);
+        games.add_player("test", player_3, None);
+        let game = games.find_mut("test").unwrap();
+        game.scores.clear();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+
+        assert!(!game.force_next_round(&host), "nothing to end");
+        game.submit_word(&host, "word".to_string(), canvas);
+        assert!(!game.force_next_round(&player_2), "not a host");
+        assert!(game.force_next_round(&host));
+        assert!(matches!(
+            game.stage,
+            GameStage::PlayerChoosing { player_id } if player_id == player_2
+        ));
+        let outcome = game.last_turn_outcome().unwrap();
+        assert_eq!("word", outcome.word);
+        assert!(!outcome.guessed);
+        assert!(game.scores.is_empty(), "no points");
+    }
+
+    #[test]
+    fn game_apply_events() {
+        let mut games = Games::new(GamesConfig::default());
+        let player_1 = Uuid::new_v4();
+        let player_2 = Uuid::new_v4();
+        games.add_player("test", player_1, None);
+        games.add_player("test", player_2, None);
+        let mut game = games.find("test").unwrap().clone();
+        let entry = |player_id: Uuid, body: serde_json::Value| AuditEntry {
+            player_id,
+            at: 0,
+            event: serde_json::json!({ "body": body }),
+        };
+        let log = vec![
+            entry(
+                player_1,
+                serde_json::json!({"type": "submitWord", "word": "cat", "canvas": {"width": 100, "height": 100}}),
+            ),
+            entry(
+                player_1,
+                serde_json::json!({"type": "addDrawingSegment", "id": "a", "stroke": "red", "lineWidth": 2, "points": [{"x": 0, "y": 0}]}),
+            ),
+            entry(player_2, serde_json::json!({"type": "ping"})),
+            entry(
+                player_2,
+                serde_json::json!({"type": "guessWord", "word": "dog"}),
+            ),
+            entry(
+                player_2,
+                serde_json::json!({"type": "guessWord", "word": "cat"}),
+            ),
+        ];
+
+        game.apply_events(&log).expect("Replay");
+        assert!(matches!(
+            game.stage,
+            GameStage::PlayerChoosing { player_id } if player_id == player_2
+        ));
+        assert_eq!(Some(&1), game.scores.get(&player_2));
+        assert_eq!(None, game.scores.get(&player_1));
+        assert_eq!(1, game.turn_timeline(0).unwrap().len(), "drawn segments");
+
+        let broken = vec![entry(player_2, serde_json::json!({"type": "guessWord"}))];
+        assert!(game.apply_events(&broken).is_err());
+    }
+
+    #[test]
+    fn game_palette() {
+        let mut games = Games::new(GamesConfig::default());
+        let game_id = games.reserve_id(GameSettings {
+            palette: vec!["rgb(0, 0, 0)".to_string(), "#df362d".to_string()],
+            ..GameSettings::default()
+        });
+        let player_id = Uuid::new_v4();
+        games.add_player(&game_id, player_id, None);
+        let game = games.find_mut(&game_id).unwrap();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+        game.submit_word(&player_id, "word".to_string(), canvas);
+        let segment = |id: &str, stroke: &str| {
+            let json = format!(
+                r#"{{"id":"{}","stroke":"{}","lineWidth":2,"points":[{{"x":0,"y":0}}]}}"#,
+                id, stroke
+            );
+            serde_json::from_str::<DrawingSegment>(&json).expect("Parse segment")
+        };
+        let stroke = |segment: &DrawingSegment| match &segment.kind {
+            SegmentKind::Line { stroke, .. } => stroke.clone(),
+            _ => panic!("Expected a line"),
+        };
+
+        let mut reddish = segment("a", "rgb(200, 40, 40)");
+        assert!(game.add_segment(&player_id, &mut reddish).is_some());
+        assert_eq!("#df362d", stroke(&reddish), "snapped");
+
+        let mut black = segment("b", "rgb(0, 0, 0)");
+        assert!(game.add_segment(&player_id, &mut black).is_some());
+        assert_eq!("rgb(0, 0, 0)", stroke(&black), "allowed");
+
+        let mut unknown = segment("c", "papayawhip");
+        assert!(
+            game.add_segment(&player_id, &mut unknown).is_none(),
+            "rejected"
+        );
+
+        // Empty palette is unrestricted
+        game.settings.palette.clear();
+        let mut any = segment("d", "papayawhip");
+        assert!(game.add_segment(&player_id, &mut any).is_some());
+        assert_eq!("papayawhip", stroke(&any));
+    }
+
+    #[test]
+    fn game_blocked_words() {
+        let mut games = Games::new(GamesConfig {
+            blocked_words: vec!["heck".to_string(), "ice cream".to_string()],
+            ..GamesConfig::default()
+        });
+        let player_id = Uuid::new_v4();
+        games.add_player("test", player_id, None);
+        let game = games.find_mut("test").unwrap();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+
+        let res = game.submit_word(&player_id, "What the HECK".to_string(), canvas.clone());
+        assert_eq!(SubmitResult::Profanity, res);
+        assert!(matches!(game.stage, GameStage::PlayerChoosing { .. }));
+
+        for word in &["Ice cream", "ice-cream cone"] {
+            let res = game.submit_word(&player_id, word.to_string(), canvas.clone());
+            assert_eq!(SubmitResult::Profanity, res, "{}", word);
+        }
+        let res = game.submit_word(&player_id, "Ice".to_string(), canvas.clone());
+        assert_eq!(SubmitResult::Accepted, res, "part of an entry");
+        game.stage = GameStage::PlayerChoosing { player_id };
+
+        let res = game.submit_word(&player_id, "Checkers".to_string(), canvas);
+        assert_eq!(SubmitResult::Accepted, res);
+    }
+
+    #[test]
+    fn game_age() {
+        let mut games = Games::new(GamesConfig::default());
+        games.add_player("fresh", Uuid::new_v4(), None);
+        games.add_player("old", Uuid::new_v4(), None);
+        let now = Instant::now();
+        games.find_mut("old").unwrap().created_at = now - Duration::from_secs(3600);
+
+        assert!(games.find("fresh").unwrap().age(now) < Duration::from_secs(1));
+        assert!(games.find("old").unwrap().age(now) >= Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn game_away_player_skipped() {
+        let mut games = Games::new(GamesConfig::default());
+        let ids = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+        for id in &ids {
+            games.add_player("test", *id, None);
+        }
+        let game = games.find_mut("test").unwrap();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+
+        assert!(game.set_away(&ids[1], true));
+        assert!(!game.set_away(&ids[1], true), "unchanged");
+        game.submit_word(&ids[0], "word".to_string(), canvas);
+        game.give_up(&ids[1]);
+        game.give_up(&ids[2]);
+        assert_eq!(ids[2], game.stage.player_id(), "away player skipped");
+
+        // Choosing player stepping away passes the turn
+        assert!(game.set_away(&ids[2], true));
+        assert_eq!(ids[0], game.stage.player_id());
+
+        // Everybody is away
+        assert!(game.set_away(&ids[0], true));
+        assert_eq!(ids[0], game.stage.player_id());
+    }
+
+    #[test]
+    fn game_roster_since() {
+        let mut games = Games::new(GamesConfig::default());
+        let player_1 = Uuid::new_v4();
+        let player_2 = Uuid::new_v4();
+        let player_3 = Uuid::new_v4();
+        games.add_player("test", player_1, None);
+        games.add_player("test", player_2, None);
+        let known_version = games.find("test").unwrap().roster_version;
+
+        games.add_player("test", player_3, None);
+        games.remove_player(&player_2);
+        let game = games.find("test").unwrap();
+        let delta = game.roster_since(known_version);
+        assert_eq!(known_version + 2, delta.version);
+        assert!(!delta.full);
+        let added: Vec<Uuid> = delta.added.iter().map(|p| p.id).collect();
+        assert_eq!(vec![player_3], added, "added");
+        assert_eq!(vec![player_2], delta.removed, "removed");
+
+        let delta = game.roster_since(delta.version);
+        assert!(
+            delta.added.is_empty() && delta.removed.is_empty(),
+            "up to date"
+        );
+
+        // Full roster when changes are no longer kept
+        let mut game = game.clone();
+        Arc::make_mut(&mut game.roster_changes).drain(..2);
+        let delta = game.roster_since(0);
+        assert!(delta.full);
+        assert_eq!(2, delta.added.len());
+    }
+
+    #[test]
+    fn game_round_summary() {
+        let mut games = Games::new(GamesConfig::default());
+        let game_id = games.reserve_id(GameSettings {
+            round_summary_secs: Some(5),
+            ..GameSettings::default()
+        });
+        let player_1 = Uuid::new_v4();
+        let player_2 = Uuid::new_v4();
+        games.add_player(&game_id, player_1, None);
+        games.add_player(&game_id, player_2, None);
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+        let game = games.find_mut(&game_id).unwrap();
+        game.submit_word(&player_1, "Paris".to_string(), canvas);
+        let ended_at = Instant::now();
+        assert_eq!(GuessResult::Correct, game.guess_word(&player_2, "paris"));
+        assert!(matches!(
+            &game.stage,
+            GameStage::RoundSummary { player_id, word, guessed_by, .. }
+                if *player_id == player_2 && word == "Paris" && *guessed_by == Some(player_2)
+        ));
+
+        assert!(games
+            .advance_round_summaries(ended_at + Duration::from_secs(4))
+            .is_empty());
+        let game = games.find(&game_id).unwrap();
+        assert!(matches!(game.stage, GameStage::RoundSummary { .. }), "held");
+
+        let advanced = games.advance_round_summaries(ended_at + Duration::from_secs(6));
+        assert_eq!(1, advanced.len());
+        assert!(matches!(
+            advanced[0].stage,
+            GameStage::PlayerChoosing { player_id } if player_id == player_2
+        ));
+    }
+
+    #[test]
+    fn game_drawing_points_budget() {
+        let mut games = Games::new(GamesConfig {
+            max_drawing_points: Some(20),
+            ..GamesConfig::default()
+        });
+        let player_id = Uuid::new_v4();
+        games.add_player("test", player_id, None);
+        let game = games.find_mut("test").unwrap();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+        let segment = |id: &str| {
+            let points: Vec<String> = (0..10).map(|x| format!(r#"{{"x":{},"y":0}}"#, x)).collect();
+            let json = format!(
+                r#"{{"id":"{}","stroke":"red","lineWidth":2,"points":[{}]}}"#,
+                id,
+                points.join(",")
+            );
+            serde_json::from_str::<DrawingSegment>(&json).expect("Parse segment")
+        };
+
+        game.submit_word(&player_id, "word".to_string(), canvas);
+        for id in &["a", "b", "c"] {
+            game.add_segment(&player_id, &mut segment(id));
+        }
+
+        let mut points = vec![];
+        game.iter_drawing(|s| points.push(s.point_count()));
+        assert!(
+            points.iter().sum::<usize>() <= 20,
+            "fits budget: {:?}",
+            points
+        );
+        assert_eq!(10, points[2], "recent stroke intact");
+    }
+
+    #[test]
+    fn simplify_keeps_line_ends() {
+        let json = r#"{"id":"l","stroke":"red","lineWidth":2,"points":[{"x":0,"y":0},{"x":1,"y":0},{"x":2,"y":0},{"x":3,"y":0}]}"#;
+        let mut segment = serde_json::from_str::<DrawingSegment>(json).expect("Parse segment");
+        assert!(segment.simplify());
+        match &segment.kind {
+            SegmentKind::Line { points, .. } => {
+                let xs: Vec<i32> = points.iter().map(|p| p.x).collect();
+                assert_eq!(vec![0, 2, 3], xs);
+            }
+            _ => panic!("Expected line"),
+        }
+    }
+
+    #[test]
+    fn game_give_up() {
+        let mut games = Games::new(GamesConfig::default());
+        let player_1 = Uuid::new_v4();
+        let player_2 = Uuid::new_v4();
+        let player_3 = Uuid::new_v4();
+        games.add_player("test", player_1, None);
+        games.add_player("test", player_2, None);
+        games.add_player("test", player_3, None);
+        let game = games.find_mut("test").unwrap();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+
+        assert_eq!(
+            GiveUpResult::NotAllowed,
+            game.give_up(&player_2),
+            "not drawing"
+        );
+        game.submit_word(&player_1, "word".to_string(), canvas);
+        assert_eq!(GiveUpResult::NotAllowed, game.give_up(&player_1), "drawer");
+        assert_eq!(GiveUpResult::Waiting, game.give_up(&player_2));
+        assert_eq!(GiveUpResult::Waiting, game.give_up(&player_2), "same vote");
+        assert_eq!(GiveUpResult::RoundEnded, game.give_up(&player_3));
+
+        assert_eq!("word", game.history[0].word, "word revealed");
+        assert!(game.history[0].player_guessed.is_none(), "nobody guessed");
+        assert!(game.scores.is_empty(), "no points");
+        assert_eq!(player_2, game.active_player_id(), "next player");
+    }
+
+    #[test]
+    fn game_give_up_after_leaving() {
+        let mut games = Games::new(GamesConfig::default());
+        let ids = [
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+        ];
+        for id in &ids {
+            games.add_player("test", *id, None);
+        }
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+        let game = games.find_mut("test").unwrap();
+        game.submit_word(&ids[0], "word".to_string(), canvas);
+        assert_eq!(GiveUpResult::Waiting, game.give_up(&ids[1]));
+        games.remove_player(&ids[1]);
+
+        let game = games.find_mut("test").unwrap();
+        assert_eq!(
+            GiveUpResult::Waiting,
+            game.give_up(&ids[2]),
+            "remaining guesser hasn't given up"
+        );
+        assert_eq!(GiveUpResult::RoundEnded, game.give_up(&ids[3]));
+    }
+
+    #[test]
+    fn game_canvas_size() {
+        let mut games = Games::new(GamesConfig::default());
+        let player_id = Uuid::new_v4();
+        games.add_player("test", player_id, None);
+        let game = games.find_mut("test").unwrap();
+        let mut submit = |width, height| {
+            game.submit_word(&player_id, "word".to_string(), CanvasSize { width, height })
+        };
+
+        assert_eq!(SubmitResult::InvalidCanvas, submit(0, 100), "zero width");
+        assert_eq!(SubmitResult::InvalidCanvas, submit(100, 0), "zero height");
+        assert_eq!(
+            SubmitResult::InvalidCanvas,
+            submit(100, MAX_CANVAS_SIZE + 1),
+            "oversized"
+        );
+        assert_eq!(SubmitResult::Accepted, submit(500, 500), "valid");
+    }
+
+    #[test]
+    fn game_mark_close() {
+        let mut games = Games::new(GamesConfig::default());
+        let player_1 = Uuid::new_v4();
+        let player_2 = Uuid::new_v4();
+        games.add_player("test", player_1, None);
+        games.add_player("test", player_2, None);
+        let game = games.find_mut("test").unwrap();
+        let now = Instant::now();
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+
+        assert!(!game.mark_close(&player_1, &player_2, now), "not drawing");
+        game.submit_word(&player_1, "word".to_string(), canvas);
+        assert!(!game.mark_close(&player_2, &player_1, now), "only drawer");
+        assert!(!game.mark_close(&player_1, &player_1, now), "not self");
+        assert!(game.mark_close(&player_1, &player_2, now), "marked");
+        assert!(
+            !game.mark_close(&player_1, &player_2, now + Duration::from_secs(1)),
+            "rate limited"
+        );
+        assert!(
+            game.mark_close(&player_1, &player_2, now + MARK_CLOSE_INTERVAL),
+            "marked again"
+        );
+    }
+
+    #[test]
+    fn games_restore_removed_player() {
+        let mut games = Games::new(GamesConfig::default());
+        let player_id = Uuid::new_v4();
+        games.add_player("test", Uuid::new_v4(), None);
+        let (_, player) = games.add_player("test", player_id, None).expect("Join");
+
+        games.remove_player(&player_id);
+        assert_eq!(
+            Some(player_id),
+            games.resume_player_id("test", Some(player_id)),
+            "resumed"
+        );
+        assert_eq!(None, games.resume_player_id("other", Some(player_id)));
+        let (_, restored) = games.add_player("test", player_id, None).expect("Join");
+        assert_eq!(player.nickname, restored.nickname, "nickname restored");
+
+        // Restoring is disabled with zero duration
+        let mut games = Games::new(GamesConfig {
+            restore_removed_within: Duration::from_secs(0),
+            ..GamesConfig::default()
+        });
+        games.add_player("test", Uuid::new_v4(), None);
+        games.add_player("test", player_id, None);
+        games.remove_player(&player_id);
+        assert_eq!(None, games.resume_player_id("test", Some(player_id)));
+    }
+
     #[test]
     fn games_lifecycle() {
-        let mut games = Games::new();
+        let mut games = Games::new(GamesConfig::default());
         let game_id = "test".to_string();
         let player_id = Uuid::new_v4();
         let player_id_2 = Uuid::new_v4();
@@ -380,7 +3698,7 @@ mod tests {
 
         {
             // Create a game
-            let (game, player) = games.add_player(&game_id, player_id.clone(), None);
+            let (game, player) = games.add_player(&game_id, player_id, None).expect("Join");
             assert_eq!(player_id, player.id, "player id");
             assert_eq!(1, game.players.len(), "players in the game");
             match game.stage {
@@ -395,7 +3713,7 @@ mod tests {
 
         {
             // Add another player
-            let (game, _) = games.add_player(&game_id, player_id_2.clone(), None);
+            let (game, _) = games.add_player(&game_id, player_id_2, None).expect("Join");
             assert_eq!(2, game.players.len(), "players in the game");
         }
 
@@ -406,7 +3724,7 @@ mod tests {
             let res = game
                 .unwrap()
                 .submit_word(&player_id_2, word.clone(), canvas.clone());
-            assert_eq!(false, res);
+            assert_eq!(SubmitResult::NotYourTurn, res);
         }
 
         {
@@ -415,7 +3733,7 @@ mod tests {
             assert!(game.is_some());
             let game = game.unwrap();
             let res = game.submit_word(&player_id, word.clone(), canvas.clone());
-            assert!(res);
+            assert_eq!(SubmitResult::Accepted, res);
             match game.stage {
                 GameStage::PlayerDrawing {
                     player_id: p_id, ..
@@ -433,7 +3751,7 @@ mod tests {
             let game = games.find_mut(&game_id);
             assert!(game.is_some());
             let res = game.unwrap().guess_word(&player_id_2, "wrong");
-            assert_eq!(false, res);
+            assert_eq!(GuessResult::Wrong, res);
         }
 
         {
@@ -450,7 +3768,7 @@ mod tests {
             assert!(game.is_some());
             let game = game.unwrap();
             let res = game.guess_word(&player_id_2, &word);
-            assert!(res);
+            assert_eq!(GuessResult::Correct, res);
             match game.stage {
                 GameStage::PlayerChoosing {
                     player_id: p_id, ..
@@ -467,7 +3785,8 @@ mod tests {
             // Remove player from the game
             let modified_games = games.remove_player(&player_id_2);
             assert_eq!(1, modified_games.len(), "modified games len");
-            let game = &modified_games[0];
+            let (game, removed) = &modified_games[0];
+            assert_eq!(player_id_2, removed.id, "removed player");
             assert_eq!(1, game.players.len(), "modified game players");
             assert_eq!(player_id, game.players[0].id, "remaining player");
             match game.stage {
@@ -0,0 +1,245 @@
+//! Compact binary encoding of a drawing for reconnecting clients.
+//!
+//! Layout (all integers are LEB128 varints, signed ones are zigzag encoded):
+//!
+//! ```text
+//! snapshot = MAGIC VERSION count segment*
+//! segment  = id order kind
+//! kind     = 0 stroke line_width(signed) count x y (dx dy)*   ; line, deltas from the previous point
+//!          | 1 stamp x y size                                ; stamp
+//! string   = len utf8-bytes
+//! ```
+
+#[cfg(test)]
+use super::Point;
+use super::{DrawingSegment, SegmentKind};
+
+/// Lets clients tell snapshots apart from other binary messages, e.g. MessagePack events
+pub const MAGIC: &[u8] = b"KDS";
+const VERSION: u8 = 1;
+
+const KIND_LINE: u8 = 0;
+const KIND_STAMP: u8 = 1;
+
+/// Encode drawing segments into a binary snapshot
+pub fn encode(segments: &[DrawingSegment]) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    out.push(VERSION);
+    write_uint(&mut out, segments.len() as u64);
+    for segment in segments {
+        write_str(&mut out, &segment.id);
+        write_uint(&mut out, segment.order);
+        match &segment.kind {
+            SegmentKind::Line {
+                stroke,
+                line_width,
+                points,
+            } => {
+                out.push(KIND_LINE);
+                write_str(&mut out, stroke);
+                write_int(&mut out, *line_width as i64);
+                write_uint(&mut out, points.len() as u64);
+                let (mut x, mut y) = (0, 0);
+                for point in points {
+                    write_int(&mut out, point.x as i64 - x);
+                    write_int(&mut out, point.y as i64 - y);
+                    x = point.x as i64;
+                    y = point.y as i64;
+                }
+            }
+            SegmentKind::Stamp { stamp, at, size } => {
+                out.push(KIND_STAMP);
+                write_str(&mut out, stamp);
+                write_int(&mut out, at.x as i64);
+                write_int(&mut out, at.y as i64);
+                write_uint(&mut out, *size as u64);
+            }
+        }
+    }
+    out
+}
+
+/// Decode a binary snapshot. Return None if the snapshot is malformed.
+/// Clients have their own decoder, this one keeps the format honest in tests.
+#[cfg(test)]
+pub fn decode(bytes: &[u8]) -> Option<Vec<DrawingSegment>> {
+    let mut reader = Reader {
+        bytes: bytes.strip_prefix(MAGIC)?,
+    };
+    if reader.byte()? != VERSION {
+        return None;
+    }
+    let count = reader.uint()?;
+    let mut segments = vec![];
+    for _ in 0..count {
+        let id = reader.string()?;
+        let order = reader.uint()?;
+        let kind = match reader.byte()? {
+            KIND_LINE => {
+                let stroke = reader.string()?;
+                let line_width = reader.int()? as i32;
+                let len = reader.uint()?;
+                let mut points = vec![];
+                let (mut x, mut y) = (0, 0);
+                for _ in 0..len {
+                    x += reader.int()?;
+                    y += reader.int()?;
+                    points.push(Point {
+                        x: x as i32,
+                        y: y as i32,
+                    });
+                }
+                SegmentKind::Line {
+                    stroke,
+                    line_width,
+                    points,
+                }
+            }
+            KIND_STAMP => SegmentKind::Stamp {
+                stamp: reader.string()?,
+                at: Point {
+                    x: reader.int()? as i32,
+                    y: reader.int()? as i32,
+                },
+                size: reader.uint()? as u32,
+            },
+            _ => return None,
+        };
+        segments.push(DrawingSegment {
+            id,
+            kind,
+            merged_ids: vec![],
+            order,
+            created_order: order,
+        });
+    }
+    Some(segments)
+}
+
+fn write_uint(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_int(out: &mut Vec<u8>, value: i64) {
+    write_uint(out, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn write_str(out: &mut Vec<u8>, value: &str) {
+    write_uint(out, value.len() as u64);
+    out.extend_from_slice(value.as_bytes());
+}
+
+#[cfg(test)]
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+#[cfg(test)]
+impl Reader<'_> {
+    fn byte(&mut self) -> Option<u8> {
+        let (first, rest) = self.bytes.split_first()?;
+        self.bytes = rest;
+        Some(*first)
+    }
+
+    fn uint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn int(&mut self) -> Option<i64> {
+        let value = self.uint()?;
+        Some((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.uint()? as usize;
+        if len > self.bytes.len() {
+            return None;
+        }
+        let (value, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        String::from_utf8(value.to_vec()).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(id: &str, order: u64, points: Vec<(i32, i32)>) -> DrawingSegment {
+        DrawingSegment {
+            id: id.to_string(),
+            kind: SegmentKind::Line {
+                stroke: "rgb(0, 0, 0)".to_string(),
+                line_width: 4,
+                points: points.into_iter().map(|(x, y)| Point { x, y }).collect(),
+            },
+            merged_ids: vec![],
+            order,
+            created_order: order,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let segments = vec![
+            line("seg-1", 1, vec![(10, 20), (12, 19), (-5, 300)]),
+            DrawingSegment {
+                id: "seg-2".to_string(),
+                kind: SegmentKind::Stamp {
+                    stamp: "⭐".to_string(),
+                    at: Point { x: 40, y: -2 },
+                    size: 32,
+                },
+                merged_ids: vec![],
+                order: 7,
+                created_order: 7,
+            },
+            line("seg-3", 300, vec![]),
+        ];
+
+        let decoded = decode(&encode(&segments)).expect("Decode snapshot");
+        assert_eq!(
+            serde_json::to_value(&segments).unwrap(),
+            serde_json::to_value(&decoded).unwrap()
+        );
+    }
+
+    #[test]
+    fn smaller_than_json() {
+        let segments: Vec<DrawingSegment> = (0..50)
+            .map(|i| {
+                let points = (0..200).map(|p| (100 + p % 7, 200 + p / 3 + i)).collect();
+                line(&format!("seg-{}", i), i as u64 + 1, points)
+            })
+            .collect();
+
+        let binary = encode(&segments).len();
+        let json = serde_json::to_vec(&segments).unwrap().len();
+        assert!(binary * 5 < json, "binary={} json={}", binary, json);
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(decode(b"nope").is_none(), "magic");
+        let mut bytes = encode(&[line("seg-1", 1, vec![(1, 1)])]);
+        bytes.truncate(bytes.len() - 1);
+        assert!(decode(&bytes).is_none(), "truncated");
+    }
+}
@@ -1,10 +1,12 @@
 use std::collections::HashMap;
 use std::env;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use log::info;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use tokio::{
     sync::{mpsc, Mutex},
     time::interval,
@@ -16,16 +18,182 @@ use warp::Filter;
 mod errors;
 
 mod games;
+mod nicknames;
 
-use games::{CanvasSize, DrawingSegment, Game, Games, Player};
+use games::{
+    CanvasSize, DrawingSegment, Game, Games, GamesConfig, GuessProgress, Player, RosterDelta,
+};
 
 pub type App = Arc<Mutex<AppState>>;
 
+type WsSender = mpsc::UnboundedSender<Result<Message, warp::Error>>;
+
 /// A reference to player connection
 #[derive(Clone)]
 pub struct PlayerConn {
     pub id: usize,
-    pub tx: mpsc::UnboundedSender<Result<Message, warp::Error>>,
+    pub tx: WsSender,
+    pub traffic: Arc<ConnTraffic>,
+    pub encoding: Encoding,
+    /// Whether a turn is being replayed to the connection. Only one replay runs at a time.
+    pub replaying: Arc<AtomicBool>,
+}
+
+impl PlayerConn {
+    pub fn new(id: usize, tx: WsSender, encoding: Encoding) -> Self {
+        Self {
+            id,
+            tx,
+            traffic: Arc::new(ConnTraffic::default()),
+            encoding,
+            replaying: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Serialize and send an event to the connection
+    fn send(
+        &self,
+        response: impl Serialize,
+    ) -> Result<(), mpsc::error::SendError<Result<Message, warp::Error>>> {
+        let msg = self.encoding.encode(&response);
+        self.traffic
+            .bytes_sent
+            .fetch_add(msg.as_bytes().len() as u64, Ordering::Relaxed);
+        self.tx.send(Ok(msg))
+    }
+
+    /// Send raw bytes in a binary message
+    fn send_binary(
+        &self,
+        bytes: Vec<u8>,
+    ) -> Result<(), mpsc::error::SendError<Result<Message, warp::Error>>> {
+        self.traffic
+            .bytes_sent
+            .fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        self.tx.send(Ok(Message::binary(bytes)))
+    }
+}
+
+/// How events are encoded over the websocket. Chosen by the client when connecting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Encoding {
+    /// JSON in text messages
+    #[default]
+    Json,
+    /// MessagePack in binary messages
+    MessagePack,
+}
+
+impl Encoding {
+    fn encode(&self, response: &impl Serialize) -> Message {
+        match self {
+            Encoding::Json => {
+                let text = serde_json::to_string(response).expect("Serialize WS message");
+                Message::text(text)
+            }
+            Encoding::MessagePack => {
+                // Human readable mode keeps ids as strings like in JSON
+                let mut bytes = vec![];
+                let mut serializer = rmp_serde::Serializer::new(&mut bytes)
+                    .with_struct_map()
+                    .with_human_readable();
+                response
+                    .serialize(&mut serializer)
+                    .expect("Serialize WS message");
+                Message::binary(bytes)
+            }
+        }
+    }
+
+    /// Decode an event. Return None for messages of other kinds, e.g. text message
+    /// when MessagePack is used.
+    fn decode<T: DeserializeOwned>(&self, msg: &Message) -> Option<Result<T, String>> {
+        match self {
+            Encoding::Json => {
+                let text = msg.to_str().ok()?;
+                Some(serde_json::from_str(text).map_err(|e| e.to_string()))
+            }
+            Encoding::MessagePack if msg.is_binary() => {
+                let mut deserializer =
+                    rmp_serde::Deserializer::new(msg.as_bytes()).with_human_readable();
+                Some(T::deserialize(&mut deserializer).map_err(|e| e.to_string()))
+            }
+            Encoding::MessagePack => None,
+        }
+    }
+}
+
+/// Counts live websocket connections and caps them for the whole server
+#[derive(Debug)]
+pub struct ConnectionSlots {
+    count: Arc<AtomicUsize>,
+    /// Unlimited when None
+    max: Option<usize>,
+}
+
+impl ConnectionSlots {
+    pub fn new(max: Option<usize>) -> Self {
+        Self {
+            count: Arc::new(AtomicUsize::new(0)),
+            max,
+        }
+    }
+
+    /// Take a slot for a new connection. Return None if the server is at capacity.
+    fn try_acquire(&self) -> Option<ConnectionSlot> {
+        let max = self.max.unwrap_or(usize::MAX);
+        self.count
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                (count < max).then_some(count + 1)
+            })
+            .ok()?;
+        Some(ConnectionSlot {
+            count: self.count.clone(),
+        })
+    }
+
+    #[cfg(test)]
+    fn active(&self) -> usize {
+        self.count.load(Ordering::SeqCst)
+    }
+}
+
+/// Slot of a live connection. Released once dropped.
+struct ConnectionSlot {
+    count: Arc<AtomicUsize>,
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.count.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Bytes sent and received over a connection. Used to find misbehaving clients.
+#[derive(Debug, Default)]
+pub struct ConnTraffic {
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+}
+
+/// Traffic of a connection as shown in the admin endpoint
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ConnTrafficStats {
+    player_id: Uuid,
+    conn_id: usize,
+    bytes_sent: u64,
+    bytes_received: u64,
+}
+
+/// Game as shown in the admin endpoint
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GameOverview {
+    id: String,
+    players: usize,
+    age_secs: u64,
 }
 
 pub struct AppState {
@@ -34,9 +202,218 @@ pub struct AppState {
     connections: HashMap<Uuid, PlayerConn>,
     /// A mapping from player id to the time when WS connection ended.
     exited_players: HashMap<Uuid, Instant>,
+    /// How long to wait for exited players to come back before removing them from games.
+    /// Adjustable at runtime, e.g. raised during a deploy.
+    remove_player_after: Duration,
+    /// Guessing statistics per word across all games
+    word_stats: HashMap<String, WordStats>,
+    /// Live websocket connections across the server
+    connection_slots: ConnectionSlots,
+    /// Most points a single drawing segment can plausibly have.
+    /// Larger messages are rejected before parsing.
+    max_segment_points: usize,
+    /// When games were created recently by client address
+    room_creations: HashMap<IpAddr, Vec<Instant>>,
+    /// How many games a single address can create per minute. Unlimited when None.
+    max_rooms_per_minute: Option<usize>,
+    /// Don't limit game creation from local addresses. Enabled only for local development.
+    exempt_localhost: bool,
+}
+
+impl AppState {
+    pub fn new(games: Games) -> Self {
+        Self {
+            games,
+            connections: HashMap::new(),
+            exited_players: HashMap::new(),
+            remove_player_after: DEFAULT_REMOVE_PLAYER_AFTER,
+            word_stats: HashMap::new(),
+            connection_slots: ConnectionSlots::new(None),
+            max_segment_points: DEFAULT_MAX_SEGMENT_POINTS,
+            room_creations: HashMap::new(),
+            max_rooms_per_minute: Some(DEFAULT_MAX_ROOMS_PER_MINUTE),
+            exempt_localhost: false,
+        }
+    }
+
+    /// Record a game creation from the address. Return false if the address created too many games recently.
+    /// Local addresses are not limited during local development.
+    fn allow_room_creation(&mut self, ip: IpAddr, now: Instant) -> bool {
+        let max = match self.max_rooms_per_minute {
+            Some(_) if self.exempt_localhost && ip.is_loopback() => return true,
+            Some(max) => max,
+            None => return true,
+        };
+        // Forget creations outside of the window
+        self.room_creations.retain(|_, created| {
+            created.retain(|at| now.duration_since(*at) < ROOM_CREATION_WINDOW);
+            !created.is_empty()
+        });
+        let created = self.room_creations.entry(ip).or_default();
+        if created.len() >= max {
+            return false;
+        }
+        created.push(now);
+        true
+    }
+
+    /// Largest incoming message we are willing to parse
+    fn max_message_bytes(&self) -> usize {
+        self.max_segment_points * MAX_POINT_BYTES + MESSAGE_OVERHEAD_BYTES
+    }
+
+    /// Update word statistics with the last finished turn of the game
+    fn record_turn(&mut self, game: &Game) {
+        let outcome = match game.last_turn_outcome() {
+            Some(outcome) => outcome,
+            None => return,
+        };
+        let word = outcome.word.to_lowercase();
+
+        if !self.word_stats.contains_key(&word) && self.word_stats.len() >= MAX_WORD_STATS {
+            // Make room by forgetting the least played word
+            let least_played = self
+                .word_stats
+                .iter()
+                .min_by_key(|(_, stats)| stats.rounds)
+                .map(|(word, _)| word.clone());
+            if let Some(least_played) = least_played {
+                self.word_stats.remove(&least_played);
+            }
+        }
+        self.word_stats
+            .entry(word)
+            .or_default()
+            .record(outcome.guessed, outcome.time_taken);
+    }
+
+    /// Close connections of players who are no longer in any game.
+    /// Return ids of the players whose connections were closed.
+    fn close_orphaned_connections(&mut self) -> Vec<Uuid> {
+        let orphaned: Vec<Uuid> = self
+            .connections
+            .keys()
+            .filter(|player_id| !self.games.has_player(player_id))
+            .copied()
+            .collect();
+        for player_id in &orphaned {
+            if let Some(conn) = self.connections.remove(player_id) {
+                log::debug!("Closing orphaned conn={} of player {}", conn.id, player_id);
+                let _ = conn.tx.send(Ok(CloseReason::Left.message()));
+            }
+        }
+        orphaned
+    }
+
+    /// Return exited players that didn't come back in time
+    fn players_to_remove(&self, now: Instant) -> Vec<Uuid> {
+        self.exited_players
+            .iter()
+            .filter(|(_, exited_at)| now.duration_since(**exited_at) > self.remove_player_after)
+            .map(|(player_id, _)| *player_id)
+            .collect()
+    }
+
+    /// Send an event to all connected players of the game except the active one
+    fn notify_guessers(&self, game: &Game, event: OutgoingEvent) {
+        let active_player_id = game.active_player_id();
+        for player in &game.players {
+            if player.id == active_player_id {
+                continue;
+            }
+            if let Some(conn) = self.connections.get(&player.id) {
+                let _ = conn.send(event.clone());
+            }
+        }
+    }
+
+    /// Send an event to all connected players of the game
+    fn notify_players(&self, game: &Game, event: OutgoingEvent) {
+        for player in &game.players {
+            if let Some(conn) = self.connections.get(&player.id) {
+                let _ = conn.send(event.clone());
+            }
+        }
+    }
+}
+
+/// Aggregated guessing statistics of a word
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WordStats {
+    /// Finished rounds with this word
+    rounds: u32,
+    /// Rounds where the word was guessed
+    guessed: u32,
+    success_rate: f64,
+    /// Average time to guess the word in seconds
+    avg_guess_secs: f64,
+    #[serde(skip)]
+    total_guess_time: Duration,
+}
+
+impl WordStats {
+    fn record(&mut self, guessed: bool, time_taken: Duration) {
+        self.rounds += 1;
+        if guessed {
+            self.guessed += 1;
+            self.total_guess_time += time_taken;
+            self.avg_guess_secs = self.total_guess_time.as_secs_f64() / self.guessed as f64;
+        }
+        self.success_rate = self.guessed as f64 / self.rounds as f64;
+    }
+}
+
+/// Why server closes player connection
+#[derive(Debug, Clone, Copy)]
+pub enum CloseReason {
+    /// Player has left the game
+    Left,
+    /// Player has connected from another tab or device. Clients should not reconnect.
+    Replaced,
+    /// Game has no room for new players
+    Full,
+    /// Server has too many connections. Clients should try again later.
+    Overloaded,
+}
+
+impl CloseReason {
+    /// Close frame with the reason
+    fn message(self) -> Message {
+        match self {
+            CloseReason::Left => Message::close_with(1000u16, "left"),
+            CloseReason::Replaced => Message::close_with(4000u16, "replaced"),
+            CloseReason::Full => Message::close_with(4001u16, "full"),
+            CloseReason::Overloaded => Message::close_with(1013u16, "overloaded"),
+        }
+    }
 }
 
-const REMOVE_PLAYER_AFTER: Duration = Duration::from_secs(60 * 5);
+const DEFAULT_REMOVE_PLAYER_AFTER: Duration = Duration::from_secs(60 * 5);
+
+/// How many words to keep statistics for
+const MAX_WORD_STATS: usize = 1000;
+
+/// Longest pause between segments when replaying a turn
+const MAX_REPLAY_GAP: Duration = Duration::from_millis(500);
+
+/// Shortest pause between segments when replaying a turn
+const MIN_REPLAY_GAP: Duration = Duration::from_millis(10);
+
+/// Shortest interval between pings we reply to on a single connection
+const MIN_PING_INTERVAL: Duration = Duration::from_secs(1);
+
+const DEFAULT_MAX_SEGMENT_POINTS: usize = 10_000;
+
+const DEFAULT_MAX_ROOMS_PER_MINUTE: usize = 10;
+
+const ROOM_CREATION_WINDOW: Duration = Duration::from_secs(60);
+
+/// Generous estimate of a serialized point, e.g. `{"x":123.456789,"y":123.456789},`
+const MAX_POINT_BYTES: usize = 48;
+
+/// Room for the rest of the event around the points
+const MESSAGE_OVERHEAD_BYTES: usize = 1024;
 
 // TODO: error handling
 
@@ -55,18 +432,90 @@ async fn main() {
         Err(_) => ([127, 0, 0, 1], 3030),
     };
 
-    let app = Arc::new(Mutex::new(AppState {
-        games: Games::new(),
-        connections: HashMap::new(),
-        exited_players: HashMap::new(),
-    }));
+    let mut games_config = GamesConfig::default();
+    if let Ok(limit) = env::var("HISTORY_LIMIT") {
+        games_config.history_limit = limit.parse().expect("HISTORY_LIMIT must be a number");
+    }
+    if let Ok(ms) = env::var("MERGE_SEGMENTS_WITHIN_MS") {
+        let ms = ms
+            .parse()
+            .expect("MERGE_SEGMENTS_WITHIN_MS must be a number");
+        games_config.merge_segments_within = Some(Duration::from_millis(ms));
+    }
+    if let Ok(points) = env::var("MAX_DRAWING_POINTS") {
+        let points = points.parse().expect("MAX_DRAWING_POINTS must be a number");
+        games_config.max_drawing_points = Some(points);
+    }
+    if let Ok(secs) = env::var("END_ROUND_WITHOUT_GUESSERS_AFTER_SECS") {
+        let secs = secs
+            .parse()
+            .expect("END_ROUND_WITHOUT_GUESSERS_AFTER_SECS must be a number");
+        games_config.end_round_without_guessers_after = Some(Duration::from_secs(secs));
+    }
+    if let Ok(limit) = env::var("AUDIT_LIMIT") {
+        let limit = limit.parse().expect("AUDIT_LIMIT must be a number");
+        games_config.audit_limit = Some(limit);
+    }
+    if let Ok(words) = env::var("BLOCKED_WORDS") {
+        games_config.blocked_words = words
+            .split(',')
+            .map(|w| w.trim().to_lowercase())
+            .filter(|w| !w.is_empty())
+            .collect();
+    }
+    if let Ok(alphabet) = env::var("ROOM_CODE_ALPHABET") {
+        assert!(!alphabet.is_empty(), "ROOM_CODE_ALPHABET must not be empty");
+        games_config.room_codes.alphabet = alphabet.chars().collect();
+    }
+    if let Ok(len) = env::var("ROOM_CODE_MIN_LEN") {
+        games_config.room_codes.min_len = len.parse().expect("ROOM_CODE_MIN_LEN must be a number");
+    }
+
+    // Admin endpoints are enabled only when debug token is set
+    let debug_token = env::var("DEBUG_TOKEN").ok();
+    // Client addresses are read from X-Forwarded-For only when the server runs behind a proxy.
+    // Otherwise any client could pick its own address.
+    let trust_proxy = env::var_os("TRUST_PROXY").is_some();
+
+    let mut app_state = AppState::new(Games::new(games_config));
+    if let Ok(max) = env::var("MAX_CONNECTIONS") {
+        let max = max.parse().expect("MAX_CONNECTIONS must be a number");
+        app_state.connection_slots = ConnectionSlots::new(Some(max));
+    }
+    if let Ok(max) = env::var("MAX_ROOMS_PER_MINUTE") {
+        let max = max.parse().expect("MAX_ROOMS_PER_MINUTE must be a number");
+        app_state.max_rooms_per_minute = Some(max);
+    }
+    // Local development server runs without PORT
+    app_state.exempt_localhost = env::var_os("PORT").is_none();
+    if let Ok(points) = env::var("MAX_SEGMENT_POINTS") {
+        app_state.max_segment_points = points.parse().expect("MAX_SEGMENT_POINTS must be a number");
+    }
+
+    let app = Arc::new(Mutex::new(app_state));
     tokio::spawn(remove_players_job(app.clone()));
+    tokio::spawn(drawer_activity_job(app.clone()));
+    tokio::spawn(guessers_presence_job(app.clone()));
+    tokio::spawn(round_summary_job(app.clone()));
+    tokio::spawn(typing_job(app.clone()));
 
     let routes = filters::index()
         .or(filters::static_files())
-        .or(filters::create_game(app.clone()))
+        .or(filters::create_game(app.clone(), trust_proxy))
         .or(filters::game(app.clone()))
+        .or(filters::game_exists(app.clone()))
+        .or(filters::drawing_svg(app.clone()))
         .or(filters::sync(app.clone()))
+        .or(filters::set_remove_player_after(
+            app.clone(),
+            debug_token.clone(),
+        ))
+        .or(filters::server_notice(app.clone(), debug_token.clone()))
+        .or(filters::word_stats(app.clone(), debug_token.clone()))
+        .or(filters::conn_traffic(app.clone(), debug_token.clone()))
+        .or(filters::games_overview(app.clone(), debug_token.clone()))
+        .or(filters::game_audit(app.clone(), debug_token.clone()))
+        .or(filters::game_reports(app.clone(), debug_token.clone()))
         .with(warp::compression::gzip());
 
     info!("Listening on {:?}:{}", host, port);
@@ -77,30 +526,26 @@ async fn main() {
 
 /// Periodically scan for exited players and remove them from games.
 async fn remove_players_job(app: App) {
+    let mut interval = interval(Duration::from_secs(30));
     loop {
-        interval(Duration::from_secs(30)).tick().await;
+        interval.tick().await;
 
-        let mut remove_players = vec![];
-
-        {
+        let remove_players = {
             // Prepare a list of players to remove
-            let mut app = app.lock().await;
-            let now = Instant::now();
-            for (player_id, exited_at) in &mut app.exited_players {
-                if now.duration_since(*exited_at) > REMOVE_PLAYER_AFTER {
-                    remove_players.push(player_id.clone());
-                }
-            }
-        }
+            let app = app.lock().await;
+            app.players_to_remove(Instant::now())
+        };
 
         let mut all_modified_games = HashMap::new();
+        let mut left_players = vec![];
         {
             // Remove players from the games
             let mut app = app.lock().await;
             for player_id in &remove_players {
                 log::debug!("Removing exited player {}", player_id);
-                let modified_games = app.games.remove_player(&player_id);
-                for game in modified_games {
+                let modified_games = app.games.remove_player(player_id);
+                for (game, player) in modified_games {
+                    left_players.push((game.id.clone(), player));
                     all_modified_games.insert(game.id.clone(), game);
                 }
             }
@@ -115,31 +560,157 @@ async fn remove_players_job(app: App) {
                     game.players.len(),
                     game.id
                 );
-                for player in &game.players {
-                    if let Some(conn) = app.connections.get(&player.id) {
-                        let _ = conn.tx.send(message(OutgoingEvent {
-                            from_event_id: None,
-                            body: OutgoingEventBody::Game(game.clone()),
-                        }));
-                    }
-                }
+                app.notify_players(
+                    game,
+                    OutgoingEvent {
+                        from_event_id: None,
+                        body: OutgoingEventBody::Game(Box::new(game.clone())),
+                    },
+                );
+            }
+            for (game_id, player) in &left_players {
+                app.notify_players(
+                    &all_modified_games[game_id],
+                    OutgoingEvent {
+                        from_event_id: None,
+                        body: OutgoingEventBody::activity(ActivityKind::Left, player),
+                    },
+                );
             }
         }
 
         // Remove exited players
         let mut app = app.lock().await;
         for player_id in &remove_players {
-            app.exited_players.remove(&player_id);
+            app.exited_players.remove(player_id);
+        }
+        app.close_orphaned_connections();
+    }
+}
+
+/// Periodically let guessers know when drawing player stops drawing
+/// and forfeit rounds where drawing player draws nothing.
+async fn drawer_activity_job(app: App) {
+    let mut interval = interval(Duration::from_secs(1));
+    loop {
+        interval.tick().await;
+
+        let mut app = app.lock().await;
+        for (game, active) in app.games.update_drawer_activity(Instant::now()) {
+            app.notify_guessers(
+                &game,
+                OutgoingEvent {
+                    from_event_id: None,
+                    body: OutgoingEventBody::DrawerActivity { active },
+                },
+            );
+        }
+
+        for game in app.games.forfeit_idle_drawers(Instant::now()) {
+            app.record_turn(&game);
+            app.notify_players(
+                &game,
+                OutgoingEvent {
+                    from_event_id: None,
+                    body: OutgoingEventBody::ClearDrawing {},
+                },
+            );
+            app.notify_players(
+                &game,
+                OutgoingEvent {
+                    from_event_id: None,
+                    body: OutgoingEventBody::Game(Box::new(game.clone())),
+                },
+            );
+        }
+    }
+}
+
+/// Periodically stop typing indicators of players who went quiet.
+async fn typing_job(app: App) {
+    let mut interval = interval(Duration::from_secs(1));
+    loop {
+        interval.tick().await;
+
+        let mut app = app.lock().await;
+        for (game, player_ids) in app.games.expire_typing(Instant::now()) {
+            for player_id in player_ids {
+                app.notify_players(
+                    &game,
+                    OutgoingEvent {
+                        from_event_id: None,
+                        body: OutgoingEventBody::PlayerTyping {
+                            player_id,
+                            typing: false,
+                        },
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Periodically start next round once round summary has been shown.
+async fn round_summary_job(app: App) {
+    let mut interval = interval(Duration::from_secs(1));
+    loop {
+        interval.tick().await;
+
+        let mut app = app.lock().await;
+        for game in app.games.advance_round_summaries(Instant::now()) {
+            app.notify_players(
+                &game,
+                OutgoingEvent {
+                    from_event_id: None,
+                    body: OutgoingEventBody::Game(Box::new(game.clone())),
+                },
+            );
+        }
+    }
+}
+
+async fn guessers_presence_job(app: App) {
+    let mut interval = interval(Duration::from_secs(1));
+    loop {
+        interval.tick().await;
+
+        let mut app = app.lock().await;
+        let AppState {
+            games, connections, ..
+        } = &mut *app;
+        let modified_games = games.update_guessers_presence(Instant::now(), |player_id| {
+            connections.contains_key(player_id)
+        });
+        for (game, ended) in modified_games {
+            if ended {
+                app.record_turn(&game);
+            }
+            app.notify_players(
+                &game,
+                OutgoingEvent {
+                    from_event_id: None,
+                    body: OutgoingEventBody::ClearDrawing {},
+                },
+            );
+            app.notify_players(
+                &game,
+                OutgoingEvent {
+                    from_event_id: None,
+                    body: OutgoingEventBody::Game(Box::new(game.clone())),
+                },
+            );
         }
     }
 }
 
 mod filters {
     use std::convert::Infallible;
+    use std::net::{IpAddr, SocketAddr};
 
     use warp::http::header;
     use warp::{filters::reply, Filter};
 
+    use crate::games::GameSettings;
     use crate::SyncQuery;
 
     use super::{errors, handlers, App};
@@ -161,13 +732,52 @@ mod filters {
 
     pub fn create_game(
         app: App,
+        trust_proxy: bool,
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
         warp::post()
             .and(warp::path::end())
+            .and(warp::header::optional::<String>("accept"))
+            .and(game_settings())
+            .and(client_ip(trust_proxy))
             .and(with_app(app.clone()))
             .and_then(handlers::create_game)
     }
 
+    /// Game settings from the query string. Defaults are used when there is no query.
+    fn game_settings(
+    ) -> impl Filter<Extract = (Result<GameSettings, String>,), Error = Infallible> + Clone {
+        warp::query::raw()
+            .map(|query: String| {
+                serde_urlencoded::from_str::<GameSettings>(&query).map_err(|e| e.to_string())
+            })
+            .or(warp::any().map(|| Ok(GameSettings::default())))
+            .unify()
+    }
+
+    /// Address of the client. Behind a trusted proxy it is the last address in `X-Forwarded-For`,
+    /// the one added by the proxy itself. Otherwise it is the address of the peer.
+    fn client_ip(
+        trust_proxy: bool,
+    ) -> impl Filter<Extract = (Option<IpAddr>,), Error = Infallible> + Clone {
+        warp::header::optional::<String>("x-forwarded-for")
+            .and(warp::addr::remote())
+            .map(
+                move |forwarded: Option<String>, remote: Option<SocketAddr>| {
+                    forwarded
+                        .filter(|_| trust_proxy)
+                        .and_then(|forwarded| {
+                            forwarded
+                                .rsplit(',')
+                                .next()
+                                .and_then(|ip| ip.trim().parse().ok())
+                        })
+                        .or_else(|| remote.map(|addr| addr.ip()))
+                },
+            )
+            .or(warp::any().map(|| None))
+            .unify()
+    }
+
     pub fn game(
         app: App,
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
@@ -182,6 +792,24 @@ mod filters {
             ))
     }
 
+    pub fn game_exists(
+        app: App,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("game" / String / "exists")
+            .and(warp::get())
+            .and(with_app(app.clone()))
+            .and_then(handlers::game_exists)
+    }
+
+    pub fn drawing_svg(
+        app: App,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("game" / String / "drawing.svg")
+            .and(warp::get())
+            .and(with_app(app.clone()))
+            .and_then(handlers::drawing_svg)
+    }
+
     pub fn sync(
         app: App,
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
@@ -210,6 +838,107 @@ mod filters {
         }
     }
 
+    pub fn set_remove_player_after(
+        app: App,
+        debug_token: Option<String>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("admin" / "remove-player-after")
+            .and(warp::put())
+            .and(require_debug_token(debug_token))
+            .and(warp::body::json())
+            .and(with_app(app.clone()))
+            .and_then(handlers::set_remove_player_after)
+    }
+
+    pub fn server_notice(
+        app: App,
+        debug_token: Option<String>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("admin" / "notice")
+            .and(warp::post())
+            .and(require_debug_token(debug_token))
+            .and(warp::body::json())
+            .and(with_app(app.clone()))
+            .and_then(handlers::server_notice)
+    }
+
+    pub fn word_stats(
+        app: App,
+        debug_token: Option<String>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("admin" / "word-stats")
+            .and(warp::get())
+            .and(require_debug_token(debug_token))
+            .and(with_app(app.clone()))
+            .and_then(handlers::word_stats)
+    }
+
+    pub fn conn_traffic(
+        app: App,
+        debug_token: Option<String>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("admin" / "connections")
+            .and(warp::get())
+            .and(require_debug_token(debug_token))
+            .and(with_app(app.clone()))
+            .and_then(handlers::conn_traffic)
+    }
+
+    pub fn games_overview(
+        app: App,
+        debug_token: Option<String>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("admin" / "games")
+            .and(warp::get())
+            .and(require_debug_token(debug_token))
+            .and(with_app(app.clone()))
+            .and_then(handlers::games_overview)
+    }
+
+    pub fn game_audit(
+        app: App,
+        debug_token: Option<String>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("admin" / "games" / String / "audit")
+            .and(warp::get())
+            .and(require_debug_token(debug_token))
+            .and(with_app(app.clone()))
+            .and_then(handlers::game_audit)
+    }
+
+    pub fn game_reports(
+        app: App,
+        debug_token: Option<String>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("admin" / "games" / String / "reports")
+            .and(warp::get())
+            .and(require_debug_token(debug_token))
+            .and(with_app(app.clone()))
+            .and_then(handlers::game_reports)
+    }
+
+    /// Allow request only with `Authorization: Bearer <debug token>` header.
+    /// Pretend that the route doesn't exist otherwise.
+    fn require_debug_token(
+        debug_token: Option<String>,
+    ) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+        warp::header::optional::<String>("authorization")
+            .and_then(move |auth: Option<String>| {
+                let authorized = match (&debug_token, auth) {
+                    (Some(token), Some(auth)) => auth == format!("Bearer {}", token),
+                    _ => false,
+                };
+                async move {
+                    if authorized {
+                        Ok(())
+                    } else {
+                        Err(warp::reject::not_found())
+                    }
+                }
+            })
+            .untuple_one()
+    }
+
     fn with_app(app: App) -> impl Filter<Extract = (App,), Error = Infallible> + Clone {
         warp::any().map(move || app.clone())
     }
@@ -218,51 +947,231 @@ mod filters {
 mod handlers {
     use std::{
         collections::hash_map::Entry,
+        net::IpAddr,
         sync::atomic::{AtomicUsize, Ordering},
-        time::Instant,
+        time::{Duration, Instant, SystemTime, UNIX_EPOCH},
     };
 
     use futures::{FutureExt, StreamExt};
     use log::{error, info};
+    use serde::Deserialize;
     use tokio::sync::mpsc;
     use uuid::Uuid;
-    use warp::http::Uri;
+    use warp::http::{StatusCode, Uri};
     use warp::ws::Message;
 
-    use super::{App, PlayerConn};
+    use super::{
+        App, AppState, CloseReason, PlayerConn, MAX_REPLAY_GAP, MIN_PING_INTERVAL, MIN_REPLAY_GAP,
+    };
+    use crate::games::{
+        snapshot, AddedSegment, AuditEntry, Game, GameSettings, GiveUpResult, GuessResult, Report,
+        SubmitResult,
+    };
     use crate::{
-        message, IncomingEvent, IncomingEventBody, OutgoingEvent, OutgoingEventBody, SyncQuery,
+        ActivityKind, ConnTrafficStats, CreatedGame, GameExists, GameOverview, IncomingEvent,
+        IncomingEventBody, OutgoingEvent, OutgoingEventBody, RejectReason, RemovePlayerAfter,
+        ServerNotice, SyncQuery,
     };
 
+    /// Milliseconds since Unix epoch
+    fn unix_millis() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64
+    }
+
     /// Our global unique conn id counter.
     static NEXT_CONN_ID: AtomicUsize = AtomicUsize::new(1);
     const GAME_HTML: &str = include_str!("../ui/static/game.html");
 
-    pub async fn create_game(app: App) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
+    pub async fn create_game(
+        accept: Option<String>,
+        settings: Result<GameSettings, String>,
+        client_ip: Option<IpAddr>,
+        app: App,
+    ) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
+        let settings = match settings {
+            Ok(settings) => settings,
+            Err(err) => {
+                log::debug!("Invalid game settings: {}", err);
+                return Ok(Box::new(warp::reply::with_status(
+                    format!("Invalid game settings: {}", err),
+                    StatusCode::BAD_REQUEST,
+                )));
+            }
+        };
         let mut app = app.lock().await;
-        let game_id = app.games.reserve_id();
+        if let Some(ip) = client_ip {
+            if !app.allow_room_creation(ip, Instant::now()) {
+                log::debug!("Too many games created from {}", ip);
+                return Ok(Box::new(warp::reply::with_status(
+                    "Too many games created, try again later",
+                    StatusCode::TOO_MANY_REQUESTS,
+                )));
+            }
+        }
+        let game_id = app.games.reserve_id(settings);
         let url = format!("/game/{}", game_id);
         log::debug!("Created a new game {}", url);
-        Ok(Box::new(warp::redirect(
-            url.parse::<Uri>().expect("Parse uri"),
-        )))
-    }
 
-    pub async fn game(game_id: String, app: App) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
-        let app = app.lock().await;
-        if app.games.exists(&game_id) {
-            log::debug!("Game found {}", game_id);
-            Ok(Box::new(warp::reply::html(GAME_HTML)))
+        let wants_json = accept.is_some_and(|accept| accept.contains("application/json"));
+        if wants_json {
+            // API clients get game id in the body
+            Ok(Box::new(warp::reply::with_status(
+                warp::reply::json(&CreatedGame { game_id, url }),
+                StatusCode::CREATED,
+            )))
         } else {
-            log::debug!("Unknown game {}", game_id);
-            Ok(Box::new(warp::redirect(Uri::from_static("/"))))
+            Ok(Box::new(warp::redirect(
+                url.parse::<Uri>().expect("Parse uri"),
+            )))
+        }
+    }
+
+    pub async fn set_remove_player_after(
+        body: RemovePlayerAfter,
+        app: App,
+    ) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
+        let mut app = app.lock().await;
+        app.remove_player_after = Duration::from_secs(body.seconds);
+        info!("Exited players are removed after {}s", body.seconds);
+        Ok(Box::new(warp::reply::json(&body)))
+    }
+
+    pub async fn server_notice(
+        notice: ServerNotice,
+        app: App,
+    ) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
+        let app = app.lock().await;
+        let event = OutgoingEvent {
+            from_event_id: None,
+            body: OutgoingEventBody::ServerNotice(notice.clone()),
+        };
+        for conn in app.connections.values() {
+            let _ = conn.send(event.clone());
+        }
+        info!(
+            "Server notice sent to {} connections: {}",
+            app.connections.len(),
+            notice.text
+        );
+        Ok(Box::new(warp::reply::json(&notice)))
+    }
+
+    pub async fn word_stats(app: App) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
+        let app = app.lock().await;
+        Ok(Box::new(warp::reply::json(&app.word_stats)))
+    }
+
+    pub async fn conn_traffic(app: App) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
+        let app = app.lock().await;
+        let mut stats: Vec<ConnTrafficStats> = app
+            .connections
+            .iter()
+            .map(|(player_id, conn)| ConnTrafficStats {
+                player_id: *player_id,
+                conn_id: conn.id,
+                bytes_sent: conn.traffic.bytes_sent.load(Ordering::Relaxed),
+                bytes_received: conn.traffic.bytes_received.load(Ordering::Relaxed),
+            })
+            .collect();
+        // Heaviest connections first
+        stats.sort_by_key(|s| std::cmp::Reverse(s.bytes_sent + s.bytes_received));
+        Ok(Box::new(warp::reply::json(&stats)))
+    }
+
+    pub async fn game(game_id: String, app: App) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
+        let app = app.lock().await;
+        if app.games.exists(&game_id) {
+            log::debug!("Game found {}", game_id);
+            Ok(Box::new(warp::reply::html(GAME_HTML)))
+        } else {
+            log::debug!("Unknown game {}", game_id);
+            Ok(Box::new(warp::redirect(Uri::from_static("/"))))
+        }
+    }
+
+    pub async fn game_exists(
+        game_id: String,
+        app: App,
+    ) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
+        let app = app.lock().await;
+        let exists = app.games.exists(&game_id);
+        let joinable = exists && !app.games.is_full(&game_id);
+        Ok(Box::new(warp::reply::json(&GameExists {
+            exists,
+            joinable,
+        })))
+    }
+
+    pub async fn games_overview(app: App) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
+        let app = app.lock().await;
+        let now = Instant::now();
+        let mut games: Vec<GameOverview> = app
+            .games
+            .iter()
+            .map(|game| GameOverview {
+                id: game.id.clone(),
+                players: game.players.len(),
+                age_secs: game.age(now).as_secs(),
+            })
+            .collect();
+        // Oldest games first
+        games.sort_by_key(|g| std::cmp::Reverse(g.age_secs));
+        Ok(Box::new(warp::reply::json(&games)))
+    }
+
+    pub async fn game_audit(
+        game_id: String,
+        app: App,
+    ) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
+        let app = app.lock().await;
+        match app.games.audit(&game_id) {
+            Some(audit) => Ok(Box::new(warp::reply::json(&audit))),
+            None => Err(warp::reject::not_found()),
+        }
+    }
+
+    pub async fn game_reports(
+        game_id: String,
+        app: App,
+    ) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
+        let app = app.lock().await;
+        match app.games.reports(&game_id) {
+            Some(reports) => Ok(Box::new(warp::reply::json(&reports))),
+            None => Err(warp::reject::not_found()),
+        }
+    }
+
+    pub async fn drawing_svg(
+        game_id: String,
+        app: App,
+    ) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
+        let app = app.lock().await;
+        let svg = app.games.find(&game_id).and_then(|game| game.drawing_svg());
+        match svg {
+            Some(svg) => Ok(Box::new(warp::reply::with_header(
+                svg,
+                "content-type",
+                "image/svg+xml",
+            ))),
+            None => Err(warp::reject::not_found()),
         }
     }
 
     pub async fn sync(websocket: warp::filters::ws::WebSocket, app: App, query: SyncQuery) {
-        let player_id = query.player_id.unwrap_or(Uuid::new_v4());
+        let (resumed_player_id, slot, max_message_bytes) = {
+            let app = app.lock().await;
+            (
+                app.games.resume_player_id(&query.game_id, query.player_id),
+                app.connection_slots.try_acquire(),
+                app.max_message_bytes(),
+            )
+        };
+        let player_id = resumed_player_id.unwrap_or_else(Uuid::new_v4);
         let conn_id = NEXT_CONN_ID.fetch_add(1, Ordering::Relaxed);
-        if query.player_id.is_some() {
+        if resumed_player_id.is_some() {
             info!(
                 "Existing player {} in game {} conn={}",
                 player_id, query.game_id, conn_id
@@ -286,16 +1195,34 @@ mod handlers {
             }
         }));
 
+        // Keep the slot until the connection ends
+        let _slot = match slot {
+            Some(slot) => slot,
+            None => {
+                info!("Too many connections, rejecting conn={}", conn_id);
+                let _ = tx.send(Ok(CloseReason::Overloaded.message()));
+                return;
+            }
+        };
+
         let mut player_lifecycle = PlayerConnLifecycle {
             app: app.clone(),
-            conn: PlayerConn { id: conn_id, tx },
+            conn: PlayerConn::new(conn_id, tx, query.encoding),
             player_id,
             player_nickname: query.nickname,
-            new_player: query.player_id.is_none(),
+            new_player: resumed_player_id.is_none(),
             game_id: query.game_id,
+            left: false,
+            last_known_segment_order: query.last_known_segment_order,
+            client_id: query.client_id,
+            last_pong_at: None,
+            binary_snapshot: query.binary_snapshot,
+            max_message_bytes,
         };
 
-        player_lifecycle.init().await;
+        if !player_lifecycle.init().await {
+            return;
+        }
 
         // Read player messages
         while let Some(result) = ws_rx.next().await {
@@ -314,6 +1241,17 @@ mod handlers {
         player_lifecycle.disconnected().await;
     }
 
+    /// Why the player cannot change the drawing at the moment. None if they are drawing.
+    fn drawing_rejection(game: &Game, player_id: &Uuid) -> Option<RejectReason> {
+        if !game.is_drawing() {
+            Some(RejectReason::WrongStage)
+        } else if game.active_player_id() != *player_id {
+            Some(RejectReason::NotYourTurn)
+        } else {
+            None
+        }
+    }
+
     struct PlayerConnLifecycle {
         app: App,
         conn: PlayerConn,
@@ -321,97 +1259,332 @@ mod handlers {
         player_nickname: Option<String>,
         new_player: bool,
         game_id: String,
+        /// Whether player has intentionally left the game
+        left: bool,
+        /// Order of the last drawing change the player has seen before reconnecting
+        last_known_segment_order: Option<u64>,
+        /// Stable identity of the browser across games
+        client_id: Option<Uuid>,
+        /// When we last replied to a ping
+        last_pong_at: Option<Instant>,
+        /// Whether client can read binary drawing snapshots
+        binary_snapshot: bool,
+        /// Largest incoming message we are willing to parse. Fixed when the connection opens.
+        max_message_bytes: usize,
     }
 
     impl PlayerConnLifecycle {
-        /// Add our player to the game and to the known connections. Then send game info
-        async fn init(&mut self) {
+        /// Add our player to the game and to the known connections. Then send game info.
+        /// Return false and close the connection if the game is full.
+        async fn init(&mut self) -> bool {
             let mut app = self.app.lock().await;
+            // Capacity is checked under the same lock as the player is added
+            let nickname = self.player_nickname.clone().or_else(|| {
+                self.client_id
+                    .and_then(|client_id| app.games.identity_nickname(&client_id))
+            });
+            let player = match app
+                .games
+                .add_player(&self.game_id, self.player_id, nickname)
+            {
+                Some((_, player)) => player,
+                None => {
+                    info!("Game {} is full conn={}", self.game_id, self.conn.id);
+                    let _ = self.conn.tx.send(Ok(CloseReason::Full.message()));
+                    return false;
+                }
+            };
+
             // Replace existing connection if there were. We support running game in a single tab only.
-            app.connections.insert(self.player_id, self.conn.clone());
+            if let Some(replaced) = app.connections.insert(self.player_id, self.conn.clone()) {
+                log::debug!(
+                    "Player {} conn={} replaced by conn={}",
+                    self.player_id,
+                    replaced.id,
+                    self.conn.id
+                );
+                let _ = replaced.tx.send(Ok(CloseReason::Replaced.message()));
+            }
             app.exited_players.remove(&self.player_id);
 
-            let (game, player) = app.games.add_player(
-                &self.game_id,
-                self.player_id.clone(),
-                self.player_nickname.clone(),
-            );
+            // Let client know what this server supports before anything else
+            let _ = self.conn.send(OutgoingEvent {
+                from_event_id: None,
+                body: OutgoingEventBody::Hello {
+                    server_version: env!("CARGO_PKG_VERSION"),
+                    features: app.games.config().features(),
+                },
+            });
+
+            let game = app.games.find(&self.game_id).expect("Game");
 
             if self.new_player {
                 // Send this player ids only if it was new
                 self.conn
-                    .tx
-                    .send(message(OutgoingEvent {
+                    .send(OutgoingEvent {
                         from_event_id: None,
-                        body: OutgoingEventBody::YouAre { player },
-                    }))
+                        body: OutgoingEventBody::YouAre {
+                            player: player.clone(),
+                        },
+                    })
                     .expect("Send player info");
             }
 
             // TODO: Send game info to all players
             self.conn
-                .tx
-                .send(message(OutgoingEvent {
+                .send(OutgoingEvent {
                     from_event_id: None,
-                    body: OutgoingEventBody::Game(game.clone()),
-                }))
+                    body: OutgoingEventBody::Game(Box::new(game.clone())),
+                })
                 .expect("Send game");
 
             // Send current drawing
-            game.iter_drawing(|segment| {
+            let send = |body| {
                 self.conn
-                    .tx
-                    .send(message(OutgoingEvent {
+                    .send(OutgoingEvent {
                         from_event_id: None,
-                        body: OutgoingEventBody::AddDrawingSegment(segment.clone()),
-                    }))
+                        body,
+                    })
                     .expect("Send segment");
-            });
+            };
+            // Send only changes that the player hasn't seen yet
+            let known_order = self.last_known_segment_order.unwrap_or(0);
+            if let Some(changes) = game.drawing_changes(known_order) {
+                if changes.clear && self.binary_snapshot {
+                    // Whole drawing in a compact binary message
+                    self.conn
+                        .send_binary(snapshot::encode(&changes.segments))
+                        .expect("Send snapshot");
+                } else if changes.clear {
+                    // Whole drawing in a single message
+                    send(OutgoingEventBody::DrawingSnapshot {
+                        segments: changes.segments,
+                    });
+                } else {
+                    for segment_id in changes.removed_ids {
+                        send(OutgoingEventBody::RemoveDrawingSegment { segment_id });
+                    }
+                    for segment in changes.segments {
+                        send(OutgoingEventBody::AddDrawingSegment(segment));
+                    }
+                }
+            }
+
+            if self.new_player {
+                let game = game.clone();
+                app.notify_players(
+                    &game,
+                    OutgoingEvent {
+                        from_event_id: None,
+                        body: OutgoingEventBody::activity(ActivityKind::Joined, &player),
+                    },
+                );
+            }
+            if let Some(client_id) = self.client_id {
+                app.games.remember_identity(client_id, player.nickname);
+            }
 
             log::debug!("Player {} initialized", self.player_id);
+            true
+        }
+
+        /// Let the player know that their action was dropped due to game rules
+        fn reject(
+            &self,
+            from_event_id: Option<String>,
+            action: &'static str,
+            reason: RejectReason,
+        ) {
+            let _ = self.conn.send(OutgoingEvent {
+                from_event_id,
+                body: OutgoingEventBody::ActionRejected { action, reason },
+            });
+        }
+
+        /// Update away status of the player and let everybody know if it changed
+        async fn set_away(&self, away: bool) {
+            let game = {
+                let mut app = self.app.lock().await;
+                let game = app.games.find_mut(&self.game_id).expect("Game");
+                if !game.set_away(&self.player_id, away) {
+                    return;
+                }
+                game.clone()
+            };
+            self.notify_all(OutgoingEvent {
+                from_event_id: None,
+                body: OutgoingEventBody::Game(Box::new(game)),
+            })
+            .await;
+            log::debug!("Player {} away={}", self.player_id, away);
+        }
+
+        /// Record applied incoming event for bug reports if enabled
+        async fn record_audit(&self, raw: &serde_json::Value) {
+            let mut app = self.app.lock().await;
+            if app.games.config().audit_limit.is_none() {
+                return;
+            }
+            let mut event = raw.clone();
+            // Chosen and guessed words must not end up in bug reports
+            if let Some(word) = event.get_mut("body").and_then(|b| b.get_mut("word")) {
+                *word = "<redacted>".into();
+            }
+            let entry = AuditEntry {
+                player_id: self.player_id,
+                at: unix_millis(),
+                event,
+            };
+            app.games.record_audit(&self.game_id, entry);
         }
 
         async fn on_message(&mut self, msg: Message) {
-            let event_str = match msg.to_str() {
-                Ok(s) => s,
-                Err(_) => {
-                    // Skip non text messages
+            if self.left {
+                // Ignore messages after player has left the game
+                return;
+            }
+            if !self.is_live().await {
+                // Connection was replaced by another one of the same player
+                log::debug!(
+                    "Ignoring message from replaced conn={} of player {}",
+                    self.conn.id,
+                    self.player_id
+                );
+                return;
+            }
+
+            self.conn
+                .traffic
+                .bytes_received
+                .fetch_add(msg.as_bytes().len() as u64, Ordering::Relaxed);
+            if msg.as_bytes().len() > self.max_message_bytes {
+                // Don't spend memory on parsing implausibly large payloads
+                error!(
+                    "Dropping message of {} bytes from player {}",
+                    msg.as_bytes().len(),
+                    self.player_id
+                );
+                self.reject(None, "message", RejectReason::TooLong);
+                return;
+            }
+
+            log::debug!("Received message {:?}", msg);
+            // Decode once, raw event is needed for the audit and for unknown event types
+            let raw: serde_json::Value = match self.conn.encoding.decode(&msg) {
+                Some(Ok(raw)) => raw,
+                Some(Err(err)) => {
+                    error!("Failed to read WS message: {} (event={:?})", err, msg);
                     return;
                 }
+                // Skip messages of other kinds
+                None => return,
             };
-            log::debug!("Received message {}", event_str);
-            let event: IncomingEvent = match serde_json::from_str(event_str) {
+            let event = match IncomingEvent::deserialize(&raw) {
                 Ok(event) => event,
                 Err(err) => {
-                    error!("Failed to read WS message: {} (event={})", err, event_str);
+                    error!("Failed to read WS message: {} (event={:?})", err, msg);
                     return;
                 }
             };
 
+            // Drawing player comes back with their strokes, see AddDrawingSegment
+            let active = matches!(
+                event.body,
+                IncomingEventBody::SubmitWord { .. } | IncomingEventBody::GuessWord { .. }
+            );
+            if active {
+                // Player is clearly back
+                self.set_away(false).await;
+            }
+
+            // Pings would push out the events worth keeping
+            let is_ping = matches!(event.body, IncomingEventBody::Ping);
+            if self.apply_event(event, &raw).await && !is_ping {
+                self.record_audit(&raw).await;
+            }
+        }
+
+        /// Apply an incoming event. Return false if it was rejected or ignored.
+        async fn apply_event(&mut self, event: IncomingEvent, raw: &serde_json::Value) -> bool {
             match event.body {
+                IncomingEventBody::Unknown => {
+                    // Serde doesn't keep the unknown tag so read it from the raw event
+                    let event_type = raw["body"]["type"].as_str().unwrap_or_default().to_string();
+                    log::debug!("Unsupported event type {}", event_type);
+                    let _ = self.conn.send(OutgoingEvent {
+                        from_event_id: event.event_id,
+                        body: OutgoingEventBody::UnsupportedEvent { event_type },
+                    });
+                }
+
                 IncomingEventBody::Ping => {
+                    let now = Instant::now();
+                    if let Some(last_pong_at) = self.last_pong_at {
+                        if now.duration_since(last_pong_at) < MIN_PING_INTERVAL {
+                            log::debug!("Ping ignored");
+                            return false;
+                        }
+                    }
+                    self.last_pong_at = Some(now);
                     self.conn
-                        .tx
-                        .send(message(OutgoingEvent {
+                        .send(OutgoingEvent {
                             from_event_id: None,
                             body: OutgoingEventBody::Pong,
-                        }))
+                        })
                         .expect("Send pong message");
                     log::debug!("Pong sent");
                 }
 
-                IncomingEventBody::AddDrawingSegment(segment) => {
-                    {
+                IncomingEventBody::AddDrawingSegment(mut segment) => {
+                    let (added, activity, came_back) = {
                         // Add segment to the state
                         let mut app = self.app.lock().await;
                         let game = app.games.find_mut(&self.game_id).expect("Game");
-                        game.add_segment(segment.clone());
+                        let added = match game.add_segment(&self.player_id, &mut segment) {
+                            Some(added) => added,
+                            // Return when segment wasn't added
+                            None => {
+                                let reason = drawing_rejection(game, &self.player_id)
+                                    .unwrap_or(RejectReason::InvalidSegment);
+                                self.reject(event.event_id, "addDrawingSegment", reason);
+                                return false;
+                            }
+                        };
+                        // Player is clearly back. Flag is checked in the same lock on every stroke.
+                        let came_back = game.set_away(&self.player_id, false).then(|| game.clone());
+                        (added, game.drawer_activity(Instant::now()), came_back)
+                    };
+
+                    if let Some(game) = came_back {
+                        self.notify_all(OutgoingEvent {
+                            from_event_id: None,
+                            body: OutgoingEventBody::Game(Box::new(game)),
+                        })
+                        .await;
+                        log::debug!("Player {} away=false", self.player_id);
+                    }
+
+                    if let Some(active) = activity {
+                        self.notify_others(OutgoingEvent {
+                            from_event_id: None,
+                            body: OutgoingEventBody::DrawerActivity { active },
+                        })
+                        .await;
                     }
 
                     // Let others know
+                    let body = match added {
+                        AddedSegment::Stored => OutgoingEventBody::AddDrawingSegment(segment),
+                        AddedSegment::Merged { into_segment_id } => {
+                            OutgoingEventBody::MergeDrawingSegment {
+                                into_segment_id,
+                                segment,
+                            }
+                        }
+                    };
                     self.notify_others(OutgoingEvent {
                         from_event_id: None,
-                        body: OutgoingEventBody::AddDrawingSegment(segment),
+                        body,
                     })
                     .await;
                     log::debug!("Added drawing segment to other players notified");
@@ -422,6 +1595,10 @@ mod handlers {
                         // Remove segment from the state
                         let mut app = self.app.lock().await;
                         let game = app.games.find_mut(&self.game_id).expect("Game");
+                        if let Some(reason) = drawing_rejection(game, &self.player_id) {
+                            self.reject(event.event_id, "removeDrawingSegment", reason);
+                            return false;
+                        }
                         game.remove_segment(&segment_id);
                     }
 
@@ -434,13 +1611,42 @@ mod handlers {
                     log::debug!("Removed drawing segment to other players notified");
                 }
 
+                IncomingEventBody::EndSegment { id } => {
+                    let mut app = self.app.lock().await;
+                    let game = app.games.find_mut(&self.game_id).expect("Game");
+                    if let Some(reason) = drawing_rejection(game, &self.player_id) {
+                        self.reject(event.event_id, "endSegment", reason);
+                        return false;
+                    }
+                    game.end_segment(&self.player_id, &id);
+                }
+
                 IncomingEventBody::SubmitWord { word, canvas } => {
                     let game = {
                         let mut app = self.app.lock().await;
                         let game = app.games.find_mut(&self.game_id).expect("Game");
-                        if !game.submit_word(&self.player_id, word, canvas) {
+                        let res = game.submit_word(&self.player_id, word, canvas);
+                        if res == SubmitResult::Profanity {
+                            let _ = self.conn.send(OutgoingEvent {
+                                from_event_id: event.event_id.clone(),
+                                body: OutgoingEventBody::InvalidWord {
+                                    reason: "profanity",
+                                },
+                            });
+                        }
+                        let reason = match res {
+                            SubmitResult::Accepted => None,
+                            SubmitResult::WrongStage => Some(RejectReason::WrongStage),
+                            SubmitResult::NotYourTurn => Some(RejectReason::NotYourTurn),
+                            SubmitResult::TooLong => Some(RejectReason::TooLong),
+                            SubmitResult::InvalidCanvas => Some(RejectReason::InvalidCanvas),
+                            SubmitResult::Profanity => Some(RejectReason::Profanity),
+                        };
+                        if let Some(reason) = reason {
                             // Return when game wasn't changed
-                            return;
+                            log::debug!("Player {} word rejected: {:?}", self.player_id, res);
+                            self.reject(event.event_id, "submitWord", reason);
+                            return false;
                         }
                         game.clone()
                     };
@@ -452,10 +1658,18 @@ mod handlers {
                     })
                     .await;
 
+                    if let Some(player) = game.player(&self.player_id) {
+                        self.notify_all(OutgoingEvent {
+                            from_event_id: None,
+                            body: OutgoingEventBody::activity(ActivityKind::RoundStarted, player),
+                        })
+                        .await;
+                    }
+
                     // Notify all players of games changes
                     self.notify_all(OutgoingEvent {
                         from_event_id: None,
-                        body: OutgoingEventBody::Game(game),
+                        body: OutgoingEventBody::Game(Box::new(game)),
                     })
                     .await;
 
@@ -466,51 +1680,404 @@ mod handlers {
                     let game = {
                         let mut app = self.app.lock().await;
                         let game = app.games.find_mut(&self.game_id).expect("Game");
-                        if !game.guess_word(&self.player_id, &word) {
-                            // Notify wrong guess
-                            let _ = self.conn.tx.send(message(OutgoingEvent {
-                                from_event_id: event.event_id,
-                                body: OutgoingEventBody::WrongGuess {},
-                            }));
-                            return;
+                        match game.guess_word(&self.player_id, &word) {
+                            GuessResult::Correct => {
+                                let game = game.clone();
+                                app.record_turn(&game);
+                                game
+                            }
+                            GuessResult::OutOfGuesses => {
+                                let _ = self.conn.send(OutgoingEvent {
+                                    from_event_id: event.event_id,
+                                    body: OutgoingEventBody::OutOfGuesses {},
+                                });
+                                return false;
+                            }
+                            GuessResult::Wrong => {
+                                let _ = self.conn.send(OutgoingEvent {
+                                    from_event_id: event.event_id,
+                                    body: OutgoingEventBody::WrongGuess {},
+                                });
+                                let progress = game
+                                    .guess_progress()
+                                    .map(|progress| (game.active_player_id(), progress));
+                                if let Some((drawing_player_id, progress)) = progress {
+                                    // Let drawing player know how close guessers are
+                                    if let Some(conn) = app.connections.get(&drawing_player_id) {
+                                        let _ = conn.send(OutgoingEvent {
+                                            from_event_id: None,
+                                            body: OutgoingEventBody::GuessProgress(progress),
+                                        });
+                                    }
+                                }
+                                // Guess was counted
+                                return true;
+                            }
+                            GuessResult::Repeated => {
+                                // Answer the guessing player only, nothing has changed for others
+                                let _ = self.conn.send(OutgoingEvent {
+                                    from_event_id: event.event_id,
+                                    body: OutgoingEventBody::WrongGuess {},
+                                });
+                                return false;
+                            }
+                            GuessResult::NotYourTurn => {
+                                // Drawing player knows the word
+                                self.reject(event.event_id, "guessWord", RejectReason::NotYourTurn);
+                                return false;
+                            }
+                            GuessResult::WrongStage => {
+                                let _ = self.conn.send(OutgoingEvent {
+                                    from_event_id: event.event_id,
+                                    body: OutgoingEventBody::NotGuessable {},
+                                });
+                                return false;
+                            }
                         }
-                        game.clone()
                     };
 
                     // Notify all players of games changes
+                    let activity = game
+                        .player(&self.player_id)
+                        .map(|player| OutgoingEventBody::activity(ActivityKind::Guessed, player));
                     self.notify_all(OutgoingEvent {
                         from_event_id: None,
-                        body: OutgoingEventBody::Game(game),
+                        body: OutgoingEventBody::Game(Box::new(game)),
                     })
                     .await;
+                    if let Some(activity) = activity {
+                        self.notify_all(OutgoingEvent {
+                            from_event_id: None,
+                            body: activity,
+                        })
+                        .await;
+                    }
                     log::debug!("Player {} guessed a word", self.player_id);
                 }
 
                 IncomingEventBody::AskWordTip {} => {
                     let mut app = self.app.lock().await;
                     let game = app.games.find_mut(&self.game_id).expect("Game");
+                    if !game.hints_enabled() {
+                        self.reject(event.event_id, "askWordTip", RejectReason::NotAllowed);
+                        return false;
+                    }
                     if let Some(tip) = game.ask_word_tip() {
-                        let _ = self.conn.tx.send(message(OutgoingEvent {
+                        let _ = self.conn.send(OutgoingEvent {
                             from_event_id: event.event_id,
                             body: OutgoingEventBody::WordTip { tip },
-                        }));
+                        });
                     }
 
                     log::debug!("Player {} asked a tip", self.player_id);
                 }
+
+                IncomingEventBody::ReportPlayer { player_id, reason } => {
+                    let mut app = self.app.lock().await;
+                    let report = Report {
+                        reporter_id: self.player_id,
+                        player_id,
+                        reason,
+                        at: unix_millis(),
+                    };
+                    match app.games.report_player(&self.game_id, report) {
+                        Some(true) => {}
+                        // Recorded, host was already told about the player
+                        Some(false) => return true,
+                        None => {
+                            self.reject(
+                                event.event_id,
+                                "reportPlayer",
+                                RejectReason::InvalidPlayer,
+                            );
+                            return false;
+                        }
+                    }
+                    // Reporters stay anonymous
+                    let game = app.games.find(&self.game_id).expect("Game");
+                    let host_conn = game.host_id().and_then(|id| app.connections.get(&id));
+                    if let Some(conn) = host_conn {
+                        let _ = conn.send(OutgoingEvent {
+                            from_event_id: None,
+                            body: OutgoingEventBody::PlayerReported { player_id },
+                        });
+                    }
+                    log::info!("Player {} reported in game {}", player_id, self.game_id);
+                }
+
+                IncomingEventBody::SetAway { away } => {
+                    self.set_away(away).await;
+                }
+
+                IncomingEventBody::Typing { typing } => {
+                    let changed = {
+                        let mut app = self.app.lock().await;
+                        let game = app.games.find_mut(&self.game_id).expect("Game");
+                        let changed = game.set_typing(&self.player_id, typing, Instant::now());
+                        if changed.is_none() {
+                            let reason = if !game.is_drawing() {
+                                RejectReason::WrongStage
+                            } else if game.active_player_id() == self.player_id {
+                                RejectReason::NotYourTurn
+                            } else {
+                                RejectReason::InvalidPlayer
+                            };
+                            self.reject(event.event_id, "typing", reason);
+                            return false;
+                        }
+                        changed
+                    };
+                    if changed == Some(true) {
+                        self.notify_others(OutgoingEvent {
+                            from_event_id: None,
+                            body: OutgoingEventBody::PlayerTyping {
+                                player_id: self.player_id,
+                                typing,
+                            },
+                        })
+                        .await;
+                    }
+                }
+
+                IncomingEventBody::GetWordMask => {
+                    let app = self.app.lock().await;
+                    let game = app.games.find(&self.game_id).expect("Game");
+                    if !game.hints_enabled() {
+                        self.reject(event.event_id, "getWordMask", RejectReason::NotAllowed);
+                        return false;
+                    }
+                    if let Some(mask) = game.word_mask() {
+                        let _ = self.conn.send(OutgoingEvent {
+                            from_event_id: event.event_id,
+                            body: OutgoingEventBody::WordMask { mask },
+                        });
+                    }
+                }
+
+                IncomingEventBody::GetRosterSince { version } => {
+                    let app = self.app.lock().await;
+                    let game = app.games.find(&self.game_id).expect("Game");
+                    let _ = self.conn.send(OutgoingEvent {
+                        from_event_id: event.event_id,
+                        body: OutgoingEventBody::RosterDelta(game.roster_since(version)),
+                    });
+                }
+
+                IncomingEventBody::GiveUp => {
+                    let game = {
+                        let mut app = self.app.lock().await;
+                        let game = app.games.find_mut(&self.game_id).expect("Game");
+                        match game.give_up(&self.player_id) {
+                            GiveUpResult::RoundEnded => {
+                                let game = game.clone();
+                                app.record_turn(&game);
+                                game
+                            }
+                            // Return when round continues
+                            GiveUpResult::Waiting => return true,
+                            GiveUpResult::NotAllowed => {
+                                self.reject(event.event_id, "giveUp", RejectReason::NotAllowed);
+                                return false;
+                            }
+                        }
+                    };
+
+                    self.notify_all(OutgoingEvent {
+                        from_event_id: None,
+                        body: OutgoingEventBody::ClearDrawing {},
+                    })
+                    .await;
+                    self.notify_all(OutgoingEvent {
+                        from_event_id: None,
+                        body: OutgoingEventBody::Game(Box::new(game)),
+                    })
+                    .await;
+                    log::debug!("Players gave up in game {}", self.game_id);
+                }
+
+                IncomingEventBody::ForceNextRound => {
+                    let game = {
+                        let mut app = self.app.lock().await;
+                        let game = app.games.find_mut(&self.game_id).expect("Game");
+                        if !game.force_next_round(&self.player_id) {
+                            // Return when player is not a host or there is no round to end
+                            let reason = if game.host_id() != Some(self.player_id) {
+                                RejectReason::NotHost
+                            } else {
+                                RejectReason::WrongStage
+                            };
+                            self.reject(event.event_id, "forceNextRound", reason);
+                            return false;
+                        }
+                        let game = game.clone();
+                        app.record_turn(&game);
+                        game
+                    };
+
+                    self.notify_all(OutgoingEvent {
+                        from_event_id: None,
+                        body: OutgoingEventBody::ClearDrawing {},
+                    })
+                    .await;
+                    self.notify_all(OutgoingEvent {
+                        from_event_id: None,
+                        body: OutgoingEventBody::Game(Box::new(game)),
+                    })
+                    .await;
+                    log::debug!("Host forced next round in game {}", self.game_id);
+                }
+
+                IncomingEventBody::ReplayTurn { turn_index } => {
+                    if self.conn.replaying.swap(true, Ordering::AcqRel) {
+                        self.reject(event.event_id, "replayTurn", RejectReason::ReplayInProgress);
+                        return false;
+                    }
+                    let timeline = {
+                        let app = self.app.lock().await;
+                        let game = app.games.find(&self.game_id).expect("Game");
+                        match game.turn_timeline(turn_index) {
+                            Some(timeline) => timeline,
+                            None => {
+                                self.conn.replaying.store(false, Ordering::Release);
+                                self.reject(
+                                    event.event_id,
+                                    "replayTurn",
+                                    RejectReason::UnknownTurn,
+                                );
+                                return false;
+                            }
+                        }
+                    };
+
+                    // Stream segments with original timing without blocking other messages
+                    let conn = self.conn.clone();
+                    let from_event_id = event.event_id;
+                    tokio::spawn(async move {
+                        let mut prev_offset = Duration::from_secs(0);
+                        for timed in timeline.iter() {
+                            let gap = timed.offset.checked_sub(prev_offset).unwrap_or_default();
+                            tokio::time::delay_for(gap.clamp(MIN_REPLAY_GAP, MAX_REPLAY_GAP)).await;
+                            prev_offset = timed.offset;
+
+                            let sent = conn.send(OutgoingEvent {
+                                from_event_id: from_event_id.clone(),
+                                body: OutgoingEventBody::ReplaySegment {
+                                    turn_index,
+                                    segment: timed.segment.clone(),
+                                },
+                            });
+                            if sent.is_err() {
+                                // Connection is gone
+                                return;
+                            }
+                        }
+                        conn.replaying.store(false, Ordering::Release);
+                        let _ = conn.send(OutgoingEvent {
+                            from_event_id,
+                            body: OutgoingEventBody::ReplayDone { turn_index },
+                        });
+                    });
+                    log::debug!("Player {} replays turn {}", self.player_id, turn_index);
+                }
+
+                IncomingEventBody::MarkClose { player_id } => {
+                    {
+                        let mut app = self.app.lock().await;
+                        let game = app.games.find_mut(&self.game_id).expect("Game");
+                        if !game.mark_close(&self.player_id, &player_id, Instant::now()) {
+                            // Return when mark wasn't accepted
+                            self.reject(event.event_id, "markClose", RejectReason::NotAllowed);
+                            return false;
+                        }
+                    }
+
+                    self.notify_all(OutgoingEvent {
+                        from_event_id: None,
+                        body: OutgoingEventBody::MarkedClose { player_id },
+                    })
+                    .await;
+                    log::debug!("Player {} marked {} as close", self.player_id, player_id);
+                }
+
+                IncomingEventBody::Leave => {
+                    let mut app = self.app.lock().await;
+                    // Remove player right away without waiting for the removal job
+                    let modified_games = app.games.remove_player(&self.player_id);
+                    self.remove_connection(&mut app);
+                    for (game, player) in &modified_games {
+                        app.notify_players(
+                            game,
+                            OutgoingEvent {
+                                from_event_id: None,
+                                body: OutgoingEventBody::Game(Box::new(game.clone())),
+                            },
+                        );
+                        app.notify_players(
+                            game,
+                            OutgoingEvent {
+                                from_event_id: None,
+                                body: OutgoingEventBody::activity(ActivityKind::Left, player),
+                            },
+                        );
+                    }
+
+                    let _ = self.conn.tx.send(Ok(CloseReason::Left.message()));
+                    self.left = true;
+                    log::debug!("Player {} left", self.player_id);
+                }
             }
+            true
         }
 
         async fn disconnected(&mut self) {
-            // Remove player connection that is the same as this one
+            if self.left {
+                // Player was already removed
+                log::debug!("Player {} closed conn={}", self.player_id, self.conn.id);
+                return;
+            }
+
             let mut app = self.app.lock().await;
-            if let Entry::Occupied(e) = app.connections.entry(self.player_id) {
-                if e.get().id == self.conn.id {
-                    log::debug!("Exiting player {} conn={}", self.player_id, self.conn.id);
-                    e.remove();
+            if self.remove_connection(&mut app) {
+                // Player might come back
+                app.exited_players.insert(self.player_id, Instant::now());
+
+                let dropped = app.games.find_mut(&self.game_id).and_then(|game| {
+                    game.drop_uncommitted_segment(&self.player_id)
+                        .map(|segment_id| (game.clone(), segment_id))
+                });
+                if let Some((game, segment_id)) = dropped {
+                    app.notify_players(
+                        &game,
+                        OutgoingEvent {
+                            from_event_id: None,
+                            body: OutgoingEventBody::RemoveDrawingSegment { segment_id },
+                        },
+                    );
+                }
+
+                let game = app.games.find_mut(&self.game_id).and_then(|game| {
+                    // Apply drawer disconnect policy
+                    game.drawer_disconnected(&self.player_id)
+                        .then(|| game.clone())
+                });
+                if let Some(game) = game {
+                    app.record_turn(&game);
+                    app.notify_players(
+                        &game,
+                        OutgoingEvent {
+                            from_event_id: None,
+                            body: OutgoingEventBody::ClearDrawing {},
+                        },
+                    );
+                    app.notify_players(
+                        &game,
+                        OutgoingEvent {
+                            from_event_id: None,
+                            body: OutgoingEventBody::Game(Box::new(game.clone())),
+                        },
+                    );
                 }
             }
-            app.exited_players.insert(self.player_id, Instant::now());
 
             log::debug!(
                 "Player {} disconnected conn={}",
@@ -519,13 +2086,34 @@ mod handlers {
             );
         }
 
+        /// Remove player connection that is the same as this one.
+        /// Return false if player is connected with another connection.
+        fn remove_connection(&self, app: &mut AppState) -> bool {
+            if let Entry::Occupied(e) = app.connections.entry(self.player_id) {
+                if e.get().id != self.conn.id {
+                    return false;
+                }
+                log::debug!("Exiting player {} conn={}", self.player_id, self.conn.id);
+                e.remove();
+            }
+            true
+        }
+
+        /// Return whether this connection is the current connection of the player
+        async fn is_live(&self) -> bool {
+            let app = self.app.lock().await;
+            app.connections
+                .get(&self.player_id)
+                .is_some_and(|conn| conn.id == self.conn.id)
+        }
+
         async fn notify_all(&self, event: OutgoingEvent) {
             let app = self.app.lock().await;
             let game = app.games.find(&self.game_id).expect("Game");
 
             for player in &game.players {
                 if let Some(conn) = app.connections.get(&player.id) {
-                    let _ = conn.tx.send(message(event.clone()));
+                    let _ = conn.send(event.clone());
                 }
             }
         }
@@ -535,28 +2123,1144 @@ mod handlers {
             let game = app.games.find(&self.game_id).expect("Game");
 
             for player in &game.players {
-                if self.player_id == player.id {
-                    // Do not send it to ourselves
-                    continue;
-                }
-
                 if let Some(conn) = app.connections.get(&player.id) {
-                    let _ = conn.tx.send(message(event.clone()));
+                    if conn.id == self.conn.id {
+                        // Do not send it to ourselves
+                        continue;
+                    }
+                    let _ = conn.send(event.clone());
                 }
             }
         }
     }
-}
 
-fn message(response: impl Serialize) -> Result<Message, warp::Error> {
-    let text = serde_json::to_string(&response).expect("Serialize WS message");
-    Ok(Message::text(&text))
-}
+    #[cfg(test)]
+    mod tests {
+        use std::sync::Arc;
 
-/// IncomingEvent represents every possible incoming message
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct IncomingEvent {
+        use tokio::sync::Mutex;
+
+        use super::*;
+        use crate::games::{Games, GamesConfig};
+        use crate::Encoding;
+
+        type Rx = mpsc::UnboundedReceiver<Result<Message, warp::Error>>;
+
+        fn new_app() -> App {
+            Arc::new(Mutex::new(AppState::new(
+                Games::new(GamesConfig::default()),
+            )))
+        }
+
+        async fn join(app: &App, game_id: &str) -> (PlayerConnLifecycle, Rx) {
+            join_with_encoding(app, game_id, Encoding::Json).await
+        }
+
+        async fn join_with_encoding(
+            app: &App,
+            game_id: &str,
+            encoding: Encoding,
+        ) -> (PlayerConnLifecycle, Rx) {
+            let (tx, rx) = mpsc::unbounded_channel();
+            let max_message_bytes = app.lock().await.max_message_bytes();
+            let mut lifecycle = PlayerConnLifecycle {
+                app: app.clone(),
+                conn: PlayerConn::new(NEXT_CONN_ID.fetch_add(1, Ordering::Relaxed), tx, encoding),
+                player_id: Uuid::new_v4(),
+                player_nickname: None,
+                new_player: true,
+                game_id: game_id.to_string(),
+                left: false,
+                last_known_segment_order: None,
+                client_id: None,
+                last_pong_at: None,
+                binary_snapshot: false,
+                max_message_bytes,
+            };
+            lifecycle.init().await;
+            (lifecycle, rx)
+        }
+
+        /// Drain all messages sent to the connection
+        fn received(rx: &mut Rx) -> Vec<Message> {
+            let mut messages = vec![];
+            while let Ok(msg) = rx.try_recv() {
+                messages.push(msg.expect("Message"));
+            }
+            messages
+        }
+
+        /// Read types of all text events sent to the connection
+        fn received_types(rx: &mut Rx) -> Vec<String> {
+            received(rx)
+                .iter()
+                .filter_map(|msg| msg.to_str().ok())
+                .map(|text| {
+                    let event: serde_json::Value = serde_json::from_str(text).expect("Event");
+                    event["body"]["type"].as_str().expect("Type").to_string()
+                })
+                .collect()
+        }
+
+        #[tokio::test]
+        async fn hello_first() {
+            let app = Arc::new(Mutex::new(AppState::new(Games::new(GamesConfig {
+                merge_segments_within: Some(Duration::from_millis(100)),
+                ..GamesConfig::default()
+            }))));
+            let (_, mut rx) = join(&app, "test").await;
+
+            let messages = received(&mut rx);
+            let event: serde_json::Value =
+                serde_json::from_str(messages[0].to_str().expect("Text")).expect("Event");
+            assert_eq!("hello", event["body"]["type"]);
+            assert_eq!(env!("CARGO_PKG_VERSION"), event["body"]["serverVersion"]);
+            assert_eq!(
+                serde_json::json!(["mergeSegments", "restoreRemovedPlayers"]),
+                event["body"]["features"]
+            );
+        }
+
+        #[tokio::test]
+        async fn join_full_game() {
+            let app = new_app();
+            let game_id = app.lock().await.games.reserve_id(GameSettings {
+                max_players: Some(1),
+                ..GameSettings::default()
+            });
+            let (_first, _first_rx) = join(&app, &game_id).await;
+
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            let mut second = PlayerConnLifecycle {
+                app: app.clone(),
+                conn: PlayerConn::new(
+                    NEXT_CONN_ID.fetch_add(1, Ordering::Relaxed),
+                    tx,
+                    Encoding::Json,
+                ),
+                player_id: Uuid::new_v4(),
+                player_nickname: None,
+                new_player: true,
+                game_id: game_id.clone(),
+                left: false,
+                last_known_segment_order: None,
+                client_id: None,
+                last_pong_at: None,
+                binary_snapshot: false,
+                max_message_bytes: usize::MAX,
+            };
+            assert!(!second.init().await, "not joined");
+            assert_eq!(vec![CloseReason::Full.message()], received(&mut rx));
+
+            let app = app.lock().await;
+            assert_eq!(1, app.games.find(&game_id).unwrap().players.len());
+            assert!(!app.connections.contains_key(&second.player_id));
+        }
+
+        #[tokio::test]
+        async fn replaced_connection_is_closed() {
+            let app = new_app();
+            let (mut first, mut first_rx) = join(&app, "test").await;
+            let (tx, mut second_rx) = mpsc::unbounded_channel();
+            let mut second = PlayerConnLifecycle {
+                app: app.clone(),
+                conn: PlayerConn::new(
+                    NEXT_CONN_ID.fetch_add(1, Ordering::Relaxed),
+                    tx,
+                    Encoding::Json,
+                ),
+                player_id: first.player_id,
+                player_nickname: None,
+                new_player: false,
+                game_id: "test".to_string(),
+                left: false,
+                last_known_segment_order: None,
+                client_id: None,
+                last_pong_at: None,
+                binary_snapshot: false,
+                max_message_bytes: usize::MAX,
+            };
+            second.init().await;
+
+            let messages = received(&mut first_rx);
+            assert_eq!(
+                Some(&CloseReason::Replaced.message()),
+                messages.last(),
+                "first conn closed"
+            );
+            assert_eq!(vec!["hello", "game"], received_types(&mut second_rx));
+
+            // Old connection neither receives nor causes broadcasts
+            let (mut other, mut other_rx) = join(&app, "test").await;
+            received(&mut second_rx);
+            received(&mut other_rx);
+            other
+                .on_message(Message::text(r#"{"body":{"type":"leave"}}"#))
+                .await;
+            assert!(received(&mut first_rx).is_empty(), "no broadcasts");
+            assert_eq!(vec!["game", "activity"], received_types(&mut second_rx));
+
+            first
+                .on_message(Message::text(r#"{"body":{"type":"ping"}}"#))
+                .await;
+            assert!(
+                received(&mut first_rx).is_empty(),
+                "replaced conn is ignored"
+            );
+
+            // Old connection ends but the player is still connected
+            first.disconnected().await;
+            let app = app.lock().await;
+            assert!(app.exited_players.is_empty(), "player is not exited");
+            assert!(app.connections.contains_key(&first.player_id), "connected");
+        }
+
+        /// Read all activity feed entries sent to the connection
+        fn received_activities(rx: &mut Rx) -> Vec<(String, Uuid)> {
+            received(rx)
+                .iter()
+                .filter_map(|msg| msg.to_str().ok())
+                .map(|text| serde_json::from_str::<serde_json::Value>(text).expect("Event"))
+                .filter(|event| event["body"]["type"] == "activity")
+                .map(|event| {
+                    let body = &event["body"];
+                    (
+                        body["kind"].as_str().expect("Kind").to_string(),
+                        body["playerId"]
+                            .as_str()
+                            .expect("Player")
+                            .parse()
+                            .expect("Uuid"),
+                    )
+                })
+                .collect()
+        }
+
+        #[tokio::test]
+        async fn activity_feed() {
+            let app = new_app();
+            let (mut first, mut first_rx) = join(&app, "test").await;
+            let (mut second, mut second_rx) = join(&app, "test").await;
+            assert_eq!(
+                vec![
+                    ("joined".to_string(), first.player_id),
+                    ("joined".to_string(), second.player_id),
+                ],
+                received_activities(&mut first_rx),
+                "joins"
+            );
+            received(&mut second_rx);
+
+            first
+                .on_message(Message::text(
+                    r#"{"body":{"type":"submitWord","word":"cat","canvas":{"width":500,"height":500}}}"#,
+                ))
+                .await;
+            second
+                .on_message(Message::text(
+                    r#"{"body":{"type":"guessWord","word":"cat"}}"#,
+                ))
+                .await;
+
+            assert_eq!(
+                vec![
+                    ("roundStarted".to_string(), first.player_id),
+                    ("guessed".to_string(), second.player_id),
+                ],
+                received_activities(&mut second_rx)
+            );
+        }
+
+        #[tokio::test]
+        async fn leave_removes_player() {
+            let app = new_app();
+            let (_first, mut first_rx) = join(&app, "test").await;
+            let (mut second, mut second_rx) = join(&app, "test").await;
+            received(&mut first_rx);
+            received(&mut second_rx);
+
+            second
+                .on_message(Message::text(r#"{"body":{"type":"leave"}}"#))
+                .await;
+            second.disconnected().await;
+
+            {
+                let app = app.lock().await;
+                let game = app.games.find("test").expect("Game");
+                assert_eq!(1, game.players.len(), "players left");
+                assert!(app.exited_players.is_empty(), "no exited players");
+                assert!(
+                    !app.connections.contains_key(&second.player_id),
+                    "connection removed"
+                );
+            }
+
+            assert_eq!(vec!["game", "activity"], received_types(&mut first_rx));
+            let messages = received(&mut second_rx);
+            assert_eq!(
+                Some(&CloseReason::Left.message()),
+                messages.last(),
+                "socket closed"
+            );
+        }
+
+        #[tokio::test]
+        async fn replay_turn() {
+            let app = new_app();
+            let (mut first, mut first_rx) = join(&app, "test").await;
+            let (mut second, mut second_rx) = join(&app, "test").await;
+            first
+                .on_message(Message::text(
+                    r#"{"body":{"type":"submitWord","word":"cat","canvas":{"width":500,"height":500}}}"#,
+                ))
+                .await;
+            for id in &["s1", "s2"] {
+                first
+                    .on_message(Message::text(format!(
+                        r#"{{"body":{{"type":"addDrawingSegment","id":"{}","stroke":"red","lineWidth":2,"points":[{{"x":0,"y":0}}]}}}}"#,
+                        id
+                    )))
+                    .await;
+            }
+            second
+                .on_message(Message::text(
+                    r#"{"body":{"type":"guessWord","word":"cat"}}"#,
+                ))
+                .await;
+            received(&mut first_rx);
+            received(&mut second_rx);
+
+            let replay_turn = Message::text(r#"{"body":{"type":"replayTurn","turnIndex":0}}"#);
+            // Second request arrives while the first replay is still running
+            second.on_message(replay_turn.clone()).await;
+            second.on_message(replay_turn.clone()).await;
+            let mut replayed = vec![];
+            let mut rejected = vec![];
+            loop {
+                let msg = second_rx.recv().await.expect("Message").expect("Ok");
+                let event: serde_json::Value =
+                    serde_json::from_str(msg.to_str().expect("Text")).expect("Event");
+                let body = &event["body"];
+                match body["type"].as_str().expect("Type") {
+                    "replaySegment" => {
+                        replayed.push(body["segment"]["id"].as_str().expect("Id").to_string())
+                    }
+                    "actionRejected" => {
+                        rejected.push(body["reason"].as_str().expect("Reason").to_string())
+                    }
+                    "replayDone" => break,
+                    other => panic!("Unexpected event {}", other),
+                }
+            }
+            assert_eq!(vec!["s1", "s2"], replayed, "replayed once");
+            assert_eq!(vec!["replayInProgress"], rejected);
+
+            // Next replay can start once the previous one is done
+            second.on_message(replay_turn).await;
+            let msg = second_rx.recv().await.expect("Message").expect("Ok");
+            let event: serde_json::Value =
+                serde_json::from_str(msg.to_str().expect("Text")).expect("Event");
+            assert_eq!("replaySegment", event["body"]["type"]);
+            assert!(
+                received(&mut first_rx).is_empty(),
+                "only requester gets replay"
+            );
+        }
+
+        #[tokio::test]
+        async fn diff_sync() {
+            let app = new_app();
+            let (mut first, _first_rx) = join(&app, "test").await;
+            let (mut second, mut second_rx) = join(&app, "test").await;
+            first
+                .on_message(Message::text(
+                    r#"{"body":{"type":"submitWord","word":"cat","canvas":{"width":500,"height":500}}}"#,
+                ))
+                .await;
+            let add_segment = |id: &str| {
+                Message::text(format!(
+                    r#"{{"body":{{"type":"addDrawingSegment","id":"{}","stroke":"red","lineWidth":2,"points":[{{"x":0,"y":0}}]}}}}"#,
+                    id
+                ))
+            };
+            first.on_message(add_segment("s1")).await;
+            first.on_message(add_segment("s2")).await;
+
+            // Second player has seen both segments
+            let known_order = received(&mut second_rx)
+                .iter()
+                .filter_map(|msg| msg.to_str().ok())
+                .map(|text| serde_json::from_str::<serde_json::Value>(text).expect("Event"))
+                .filter_map(|event| event["body"]["order"].as_u64())
+                .max()
+                .expect("Order");
+            second.disconnected().await;
+
+            first
+                .on_message(Message::text(
+                    r#"{"body":{"type":"removeDrawingSegment","segmentId":"s1"}}"#,
+                ))
+                .await;
+            first.on_message(add_segment("s3")).await;
+
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            let mut reconnected = PlayerConnLifecycle {
+                app: app.clone(),
+                conn: PlayerConn::new(
+                    NEXT_CONN_ID.fetch_add(1, Ordering::Relaxed),
+                    tx,
+                    Encoding::Json,
+                ),
+                player_id: second.player_id,
+                player_nickname: None,
+                new_player: false,
+                game_id: "test".to_string(),
+                left: false,
+                last_known_segment_order: Some(known_order),
+                client_id: None,
+                last_pong_at: None,
+                binary_snapshot: false,
+                max_message_bytes: usize::MAX,
+            };
+            reconnected.init().await;
+
+            let events: Vec<serde_json::Value> = received(&mut rx)
+                .iter()
+                .filter_map(|msg| msg.to_str().ok())
+                .map(|text| serde_json::from_str(text).expect("Event"))
+                .collect();
+            let types: Vec<&str> = events
+                .iter()
+                .map(|event| event["body"]["type"].as_str().expect("Type"))
+                .collect();
+            assert_eq!(
+                vec!["hello", "game", "removeDrawingSegment", "addDrawingSegment"],
+                types
+            );
+            assert_eq!("s1", events[2]["body"]["segmentId"], "removed");
+            assert_eq!("s3", events[3]["body"]["id"], "newer segment");
+        }
+
+        #[tokio::test]
+        async fn join_drawing_snapshot() {
+            let app = new_app();
+            let (mut first, _first_rx) = join(&app, "test").await;
+            first
+                .on_message(Message::text(
+                    r#"{"body":{"type":"submitWord","word":"cat","canvas":{"width":500,"height":500}}}"#,
+                ))
+                .await;
+            for id in &["s1", "s2"] {
+                first
+                    .on_message(Message::text(format!(
+                        r#"{{"body":{{"type":"addDrawingSegment","id":"{}","stroke":"red","lineWidth":2,"points":[{{"x":0,"y":0}}]}}}}"#,
+                        id
+                    )))
+                    .await;
+            }
+
+            let (_second, mut second_rx) = join(&app, "test").await;
+            let snapshots: Vec<serde_json::Value> = received(&mut second_rx)
+                .iter()
+                .filter_map(|msg| msg.to_str().ok())
+                .map(|text| serde_json::from_str::<serde_json::Value>(text).expect("Event"))
+                .filter(|event| {
+                    let event_type = &event["body"]["type"];
+                    event_type == "drawingSnapshot" || event_type == "addDrawingSegment"
+                })
+                .collect();
+            assert_eq!(1, snapshots.len(), "single message");
+            assert_eq!("drawingSnapshot", snapshots[0]["body"]["type"]);
+            let ids: Vec<&str> = snapshots[0]["body"]["segments"]
+                .as_array()
+                .expect("Segments")
+                .iter()
+                .map(|segment| segment["id"].as_str().expect("Id"))
+                .collect();
+            assert_eq!(vec!["s1", "s2"], ids);
+        }
+
+        #[tokio::test]
+        async fn join_binary_snapshot() {
+            let app = new_app();
+            let (mut first, _first_rx) = join(&app, "test").await;
+            first
+                .on_message(Message::text(
+                    r#"{"body":{"type":"submitWord","word":"cat","canvas":{"width":500,"height":500}}}"#,
+                ))
+                .await;
+            first
+                .on_message(Message::text(
+                    r#"{"body":{"type":"addDrawingSegment","id":"s1","stroke":"red","lineWidth":2,"points":[{"x":0,"y":0},{"x":5,"y":3}]}}"#,
+                ))
+                .await;
+
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            let mut second = PlayerConnLifecycle {
+                app: app.clone(),
+                conn: PlayerConn::new(
+                    NEXT_CONN_ID.fetch_add(1, Ordering::Relaxed),
+                    tx,
+                    Encoding::Json,
+                ),
+                player_id: Uuid::new_v4(),
+                player_nickname: None,
+                new_player: true,
+                game_id: "test".to_string(),
+                left: false,
+                last_known_segment_order: None,
+                client_id: None,
+                last_pong_at: None,
+                binary_snapshot: true,
+                max_message_bytes: usize::MAX,
+            };
+            second.init().await;
+
+            let messages = received(&mut rx);
+            let binary: Vec<&Message> = messages.iter().filter(|msg| msg.is_binary()).collect();
+            assert_eq!(1, binary.len(), "single binary snapshot");
+            let segments = snapshot::decode(binary[0].as_bytes()).expect("Decode snapshot");
+            assert_eq!(1, segments.len());
+            let segment = serde_json::to_value(&segments[0]).unwrap();
+            assert_eq!("s1", segment["id"]);
+            assert_eq!(
+                serde_json::json!([{"x":0,"y":0},{"x":5,"y":3}]),
+                segment["points"]
+            );
+            let json_snapshot = messages
+                .iter()
+                .filter_map(|msg| msg.to_str().ok())
+                .any(|text| text.contains("drawingSnapshot"));
+            assert!(!json_snapshot, "no JSON snapshot");
+        }
+
+        #[tokio::test]
+        async fn drop_uncommitted_segment() {
+            let app = Arc::new(Mutex::new(AppState::new(Games::new(GamesConfig {
+                drop_uncommitted_segments: true,
+                ..GamesConfig::default()
+            }))));
+            let (mut first, _first_rx) = join(&app, "test").await;
+            let (_second, mut second_rx) = join(&app, "test").await;
+            first
+                .on_message(Message::text(
+                    r#"{"body":{"type":"submitWord","word":"cat","canvas":{"width":500,"height":500}}}"#,
+                ))
+                .await;
+            for id in &["s1", "s2"] {
+                first
+                    .on_message(Message::text(format!(
+                        r#"{{"body":{{"type":"addDrawingSegment","id":"{}","stroke":"red","lineWidth":2,"points":[{{"x":0,"y":0}}]}}}}"#,
+                        id
+                    )))
+                    .await;
+            }
+            first
+                .on_message(Message::text(r#"{"body":{"type":"endSegment","id":"s1"}}"#))
+                .await;
+            received(&mut second_rx);
+
+            first.disconnected().await;
+            let events: Vec<serde_json::Value> = received(&mut second_rx)
+                .iter()
+                .filter_map(|msg| msg.to_str().ok())
+                .map(|text| serde_json::from_str(text).expect("Event"))
+                .collect();
+            assert_eq!(1, events.len());
+            assert_eq!("removeDrawingSegment", events[0]["body"]["type"]);
+            assert_eq!("s2", events[0]["body"]["segmentId"], "uncommitted dropped");
+
+            let app = app.lock().await;
+            let mut count = 0;
+            app.games
+                .find("test")
+                .expect("Game")
+                .iter_drawing(|_| count += 1);
+            assert_eq!(1, count, "committed kept");
+        }
+
+        #[tokio::test]
+        async fn guess_while_choosing() {
+            let app = new_app();
+            let (_first, _first_rx) = join(&app, "test").await;
+            let (mut second, mut second_rx) = join(&app, "test").await;
+            received(&mut second_rx);
+
+            second
+                .on_message(Message::text(
+                    r#"{"body":{"type":"guessWord","word":"cat"}}"#,
+                ))
+                .await;
+            assert_eq!(vec!["notGuessable"], received_types(&mut second_rx));
+        }
+
+        #[tokio::test]
+        async fn get_word_mask() {
+            let app = new_app();
+            let (mut first, _first_rx) = join(&app, "test").await;
+            first
+                .on_message(Message::text(
+                    r#"{"body":{"type":"submitWord","word":"Apple pie","canvas":{"width":500,"height":500}}}"#,
+                ))
+                .await;
+
+            // Guessing player joins mid-round and asks for the mask
+            let (mut second, mut second_rx) = join(&app, "test").await;
+            received(&mut second_rx);
+            second
+                .on_message(Message::text(r#"{"body":{"type":"getWordMask"}}"#))
+                .await;
+            let messages = received(&mut second_rx);
+            assert_eq!(1, messages.len());
+            let event: serde_json::Value =
+                serde_json::from_str(messages[0].to_str().unwrap()).expect("Event");
+            assert_eq!("wordMask", event["body"]["type"]);
+            assert_eq!("a***e p*e", event["body"]["mask"]);
+        }
+
+        #[tokio::test]
+        async fn ping_throttled() {
+            let app = new_app();
+            let (mut first, mut first_rx) = join(&app, "test").await;
+            received(&mut first_rx);
+
+            for _ in 0..3 {
+                first
+                    .on_message(Message::text(r#"{"body":{"type":"ping"}}"#))
+                    .await;
+            }
+            assert_eq!(vec!["pong"], received_types(&mut first_rx));
+        }
+
+        #[tokio::test]
+        async fn hints_disabled() {
+            let app = new_app();
+            let game_id = app.lock().await.games.reserve_id(GameSettings {
+                hints_disabled: true,
+                ..GameSettings::default()
+            });
+            let (mut first, _first_rx) = join(&app, &game_id).await;
+            first
+                .on_message(Message::text(
+                    r#"{"body":{"type":"submitWord","word":"Apple pie","canvas":{"width":500,"height":500}}}"#,
+                ))
+                .await;
+
+            let (mut second, mut second_rx) = join(&app, &game_id).await;
+            received(&mut second_rx);
+            second
+                .on_message(Message::text(r#"{"body":{"type":"askWordTip"}}"#))
+                .await;
+            second
+                .on_message(Message::text(r#"{"body":{"type":"getWordMask"}}"#))
+                .await;
+            assert_eq!(
+                vec!["actionRejected", "actionRejected"],
+                received_types(&mut second_rx)
+            );
+        }
+
+        #[tokio::test]
+        async fn oversized_segment() {
+            let app = new_app();
+            app.lock().await.max_segment_points = 10;
+            let (mut first, mut first_rx) = join(&app, "test").await;
+            first
+                .on_message(Message::text(
+                    r#"{"body":{"type":"submitWord","word":"cat","canvas":{"width":500,"height":500}}}"#,
+                ))
+                .await;
+            received(&mut first_rx);
+
+            let points = vec![r#"{"x":1,"y":1}"#; 1000].join(",");
+            let segment = format!(
+                r#"{{"body":{{"type":"addDrawingSegment","id":"a","stroke":"red","lineWidth":2,"points":[{}]}}}}"#,
+                points
+            );
+            first.on_message(Message::text(segment)).await;
+            let messages = received(&mut first_rx);
+            assert_eq!(1, messages.len());
+            let event: serde_json::Value =
+                serde_json::from_str(messages[0].to_str().unwrap()).expect("Event");
+            assert_eq!("actionRejected", event["body"]["type"]);
+            assert_eq!("message", event["body"]["action"]);
+            assert_eq!("tooLong", event["body"]["reason"]);
+
+            let app = app.lock().await;
+            let game = app.games.find("test").expect("Game");
+            let changes = game.drawing_changes(0).expect("Drawing");
+            assert!(changes.segments.is_empty(), "segment not stored");
+        }
+
+        #[tokio::test]
+        async fn drawing_actions_rejected() {
+            let app = new_app();
+            let (mut first, mut first_rx) = join(&app, "test").await;
+            let (mut second, mut second_rx) = join(&app, "test").await;
+            first
+                .on_message(Message::text(
+                    r#"{"body":{"type":"submitWord","word":"cat","canvas":{"width":500,"height":500}}}"#,
+                ))
+                .await;
+            first
+                .on_message(Message::text(
+                    r#"{"body":{"type":"addDrawingSegment","id":"s1","stroke":"red","lineWidth":2,"points":[{"x":0,"y":0}]}}"#,
+                ))
+                .await;
+            received(&mut first_rx);
+            received(&mut second_rx);
+
+            let rejection = |rx: &mut Rx| {
+                let messages = received(rx);
+                assert_eq!(1, messages.len());
+                let event: serde_json::Value =
+                    serde_json::from_str(messages[0].to_str().unwrap()).expect("Event");
+                assert_eq!("actionRejected", event["body"]["type"]);
+                (
+                    event["body"]["action"].as_str().unwrap().to_string(),
+                    event["body"]["reason"].as_str().unwrap().to_string(),
+                )
+            };
+
+            second
+                .on_message(Message::text(
+                    r#"{"body":{"type":"removeDrawingSegment","segmentId":"s1"}}"#,
+                ))
+                .await;
+            assert_eq!(
+                (
+                    "removeDrawingSegment".to_string(),
+                    "notYourTurn".to_string()
+                ),
+                rejection(&mut second_rx)
+            );
+            assert!(received(&mut first_rx).is_empty(), "removal not relayed");
+
+            second
+                .on_message(Message::text(r#"{"body":{"type":"endSegment","id":"s1"}}"#))
+                .await;
+            assert_eq!(
+                ("endSegment".to_string(), "notYourTurn".to_string()),
+                rejection(&mut second_rx)
+            );
+
+            first
+                .on_message(Message::text(
+                    r#"{"body":{"type":"guessWord","word":"cat"}}"#,
+                ))
+                .await;
+            assert_eq!(
+                ("guessWord".to_string(), "notYourTurn".to_string()),
+                rejection(&mut first_rx)
+            );
+
+            let app = app.lock().await;
+            let game = app.games.find("test").expect("Game");
+            let changes = game.drawing_changes(0).expect("Drawing");
+            assert_eq!(1, changes.segments.len(), "segment kept");
+        }
+
+        #[tokio::test]
+        async fn typing() {
+            let app = new_app();
+            let (mut first, mut first_rx) = join(&app, "test").await;
+            let (mut second, mut second_rx) = join(&app, "test").await;
+            first
+                .on_message(Message::text(
+                    r#"{"body":{"type":"submitWord","word":"cat","canvas":{"width":500,"height":500}}}"#,
+                ))
+                .await;
+            received(&mut first_rx);
+            received(&mut second_rx);
+
+            let typing = r#"{"body":{"type":"typing","typing":true}}"#;
+            // Drawing player's typing is not shown
+            first.on_message(Message::text(typing)).await;
+            assert!(received(&mut second_rx).is_empty(), "drawer typing");
+            let messages = received(&mut first_rx);
+            assert_eq!(1, messages.len());
+            let event: serde_json::Value =
+                serde_json::from_str(messages[0].to_str().unwrap()).expect("Event");
+            assert_eq!("actionRejected", event["body"]["type"]);
+            assert_eq!("typing", event["body"]["action"]);
+            assert_eq!("notYourTurn", event["body"]["reason"]);
+
+            second.on_message(Message::text(typing)).await;
+            second.on_message(Message::text(typing)).await;
+            let messages = received(&mut first_rx);
+            assert_eq!(1, messages.len(), "repeated typing relayed once");
+            let event: serde_json::Value =
+                serde_json::from_str(messages[0].to_str().unwrap()).expect("Event");
+            assert_eq!("playerTyping", event["body"]["type"]);
+            assert_eq!(second.player_id.to_string(), event["body"]["playerId"]);
+            assert_eq!(true, event["body"]["typing"]);
+
+            let mut app = app.lock().await;
+            let now = Instant::now();
+            assert!(app.games.expire_typing(now).is_empty(), "still typing");
+            let expired = app.games.expire_typing(now + Duration::from_secs(10));
+            assert_eq!(1, expired.len());
+            assert_eq!(vec![second.player_id], expired[0].1);
+        }
+
+        #[tokio::test]
+        async fn conn_traffic() {
+            let app = new_app();
+            let (mut first, mut first_rx) = join(&app, "test").await;
+            let sent_on_join = first.conn.traffic.bytes_sent.load(Ordering::Relaxed);
+            assert!(sent_on_join > 0, "sent on join");
+            assert_eq!(0, first.conn.traffic.bytes_received.load(Ordering::Relaxed));
+            received(&mut first_rx);
+
+            let ping = r#"{"body":{"type":"ping"}}"#;
+            first.on_message(Message::text(ping)).await;
+            assert_eq!(
+                ping.len() as u64,
+                first.conn.traffic.bytes_received.load(Ordering::Relaxed)
+            );
+            let pong = received(&mut first_rx);
+            assert_eq!(
+                sent_on_join + pong[0].as_bytes().len() as u64,
+                first.conn.traffic.bytes_sent.load(Ordering::Relaxed)
+            );
+        }
+
+        #[tokio::test]
+        async fn message_pack_encoding() {
+            let app = new_app();
+            let (mut first, mut first_rx) =
+                join_with_encoding(&app, "test", Encoding::MessagePack).await;
+            let decode = |msg: &Message| -> serde_json::Value {
+                Encoding::MessagePack
+                    .decode(msg)
+                    .expect("Binary message")
+                    .expect("Event")
+            };
+            let messages = received(&mut first_rx);
+            assert!(
+                messages.iter().all(|msg| msg.is_binary()),
+                "binary messages"
+            );
+            assert_eq!("hello", decode(&messages[0])["body"]["type"]);
+
+            // Text messages are ignored
+            first
+                .on_message(Message::text(r#"{"body":{"type":"ping"}}"#))
+                .await;
+            assert!(received(&mut first_rx).is_empty());
+
+            let submit_word = serde_json::json!({
+                "eventId": "1",
+                "body": {"type": "submitWord", "word": "cat", "canvas": {"width": 500, "height": 500}},
+            });
+            first
+                .on_message(Encoding::MessagePack.encode(&submit_word))
+                .await;
+            let game = received(&mut first_rx)
+                .iter()
+                .map(decode)
+                .find(|event| event["body"]["type"] == "game")
+                .expect("Game event");
+            assert_eq!("playerDrawing", game["body"]["stage"]["type"]);
+            assert_eq!(
+                first.player_id.to_string(),
+                game["body"]["stage"]["playerId"]
+            );
+        }
+
+        #[tokio::test]
+        async fn audit_events() {
+            let app = Arc::new(Mutex::new(AppState::new(Games::new(GamesConfig {
+                audit_limit: Some(2),
+                ..GamesConfig::default()
+            }))));
+            let (mut first, _first_rx) = join(&app, "test").await;
+            let (mut second, _second_rx) = join(&app, "test").await;
+            first
+                .on_message(Message::text(
+                    r#"{"body":{"type":"submitWord","word":"cat","canvas":{"width":500,"height":500}}}"#,
+                ))
+                .await;
+            second
+                .on_message(Message::text(
+                    r#"{"body":{"type":"guessWord","word":"dog"}}"#,
+                ))
+                .await;
+            second
+                .on_message(Message::text(r#"{"body":{"type":"ping"}}"#))
+                .await;
+            // Rejected, only the drawing player can remove segments
+            second
+                .on_message(Message::text(
+                    r#"{"body":{"type":"removeDrawingSegment","segmentId":"a"}}"#,
+                ))
+                .await;
+
+            let app = app.lock().await;
+            let audit = app.games.audit("test").unwrap();
+            assert_eq!(2, audit.len(), "bounded");
+            assert_eq!(first.player_id, audit[0].player_id);
+            assert_eq!("submitWord", audit[0].event["body"]["type"]);
+            assert_eq!(second.player_id, audit[1].player_id);
+            assert_eq!("guessWord", audit[1].event["body"]["type"]);
+            assert_eq!("<redacted>", audit[1].event["body"]["word"]);
+            assert!(audit[0].at <= audit[1].at);
+        }
+
+        #[tokio::test]
+        async fn report_player() {
+            let app = Arc::new(Mutex::new(AppState::new(Games::new(GamesConfig {
+                report_threshold: 2,
+                ..GamesConfig::default()
+            }))));
+            let (_host, mut host_rx) = join(&app, "test").await;
+            let (mut first, _first_rx) = join(&app, "test").await;
+            let (mut second, _second_rx) = join(&app, "test").await;
+            let (reported, mut reported_rx) = join(&app, "test").await;
+            received(&mut host_rx);
+            received(&mut reported_rx);
+
+            let report = Message::text(format!(
+                r#"{{"body":{{"type":"reportPlayer","playerId":"{}","reason":"spam"}}}}"#,
+                reported.player_id
+            ));
+            first.on_message(report.clone()).await;
+            first.on_message(report.clone()).await;
+            assert!(
+                received(&mut host_rx).is_empty(),
+                "same reporter counts once"
+            );
+            {
+                let app = app.lock().await;
+                let reports = app.games.reports("test").unwrap();
+                assert_eq!(2, reports.len());
+                assert_eq!(first.player_id, reports[0].reporter_id);
+                assert_eq!("spam", reports[0].reason);
+            }
+
+            second.on_message(report).await;
+            assert_eq!(vec!["playerReported"], received_types(&mut host_rx));
+            assert!(received(&mut reported_rx).is_empty(), "reported player");
+        }
+
+        #[tokio::test]
+        async fn set_away() {
+            let app = new_app();
+            let (mut first, mut first_rx) = join(&app, "test").await;
+            let (mut second, mut second_rx) = join(&app, "test").await;
+            received(&mut first_rx);
+            received(&mut second_rx);
+            let is_away = |app: &AppState, player_id: Uuid| {
+                let game = app.games.find("test").unwrap();
+                game.player(&player_id).unwrap().away
+            };
+
+            second
+                .on_message(Message::text(r#"{"body":{"type":"setAway","away":true}}"#))
+                .await;
+            assert_eq!(vec!["game"], received_types(&mut first_rx), "broadcast");
+            assert!(is_away(&*app.lock().await, second.player_id));
+
+            first
+                .on_message(Message::text(
+                    r#"{"body":{"type":"submitWord","word":"cat","canvas":{"width":500,"height":500}}}"#,
+                ))
+                .await;
+            second
+                .on_message(Message::text(
+                    r#"{"body":{"type":"guessWord","word":"dog"}}"#,
+                ))
+                .await;
+            assert!(!is_away(&*app.lock().await, second.player_id), "cleared");
+
+            // Drawing player is back once they draw
+            first
+                .on_message(Message::text(r#"{"body":{"type":"setAway","away":true}}"#))
+                .await;
+            received(&mut second_rx);
+            let segment = |id: &str| {
+                Message::text(format!(
+                    r#"{{"body":{{"type":"addDrawingSegment","id":"{}","stroke":"red","lineWidth":2,"points":[{{"x":0,"y":0}}]}}}}"#,
+                    id
+                ))
+            };
+            first.on_message(segment("a")).await;
+            assert!(!is_away(&*app.lock().await, first.player_id), "drawer back");
+            assert!(received_types(&mut second_rx).contains(&"game".to_string()));
+            first.on_message(segment("b")).await;
+            assert!(
+                !received_types(&mut second_rx).contains(&"game".to_string()),
+                "no change"
+            );
+        }
+
+        #[tokio::test]
+        async fn action_rejected() {
+            let app = new_app();
+            let (mut first, mut first_rx) = join(&app, "test").await;
+            let (mut second, mut second_rx) = join(&app, "test").await;
+            received(&mut first_rx);
+            received(&mut second_rx);
+
+            let rejection = |rx: &mut Rx| -> (String, String) {
+                let messages = received(rx);
+                assert_eq!(1, messages.len());
+                let event: serde_json::Value =
+                    serde_json::from_str(messages[0].to_str().unwrap()).expect("Event");
+                assert_eq!("actionRejected", event["body"]["type"]);
+                (
+                    event["body"]["action"].as_str().unwrap().to_string(),
+                    event["body"]["reason"].as_str().unwrap().to_string(),
+                )
+            };
+            let rejected = |action: &str, reason: &str| (action.to_string(), reason.to_string());
+
+            let segment = r#"{"body":{"type":"addDrawingSegment","id":"a","stroke":"red","lineWidth":2,"points":[{"x":0,"y":0}]}}"#;
+            first.on_message(Message::text(segment)).await;
+            assert_eq!(
+                rejected("addDrawingSegment", "wrongStage"),
+                rejection(&mut first_rx)
+            );
+
+            let submit_word = r#"{"body":{"type":"submitWord","word":"cat","canvas":{"width":500,"height":500}}}"#;
+            second.on_message(Message::text(submit_word)).await;
+            assert_eq!(
+                rejected("submitWord", "notYourTurn"),
+                rejection(&mut second_rx)
+            );
+
+            first.on_message(Message::text(submit_word)).await;
+            received(&mut first_rx);
+            received(&mut second_rx);
+
+            second.on_message(Message::text(segment)).await;
+            assert_eq!(
+                rejected("addDrawingSegment", "notYourTurn"),
+                rejection(&mut second_rx)
+            );
+
+            first
+                .on_message(Message::text(r#"{"body":{"type":"giveUp"}}"#))
+                .await;
+            assert_eq!(rejected("giveUp", "notAllowed"), rejection(&mut first_rx));
+
+            second
+                .on_message(Message::text(r#"{"body":{"type":"forceNextRound"}}"#))
+                .await;
+            assert_eq!(
+                rejected("forceNextRound", "notHost"),
+                rejection(&mut second_rx)
+            );
+
+            second
+                .on_message(Message::text(
+                    r#"{"body":{"type":"replayTurn","turnIndex":5}}"#,
+                ))
+                .await;
+            assert_eq!(
+                rejected("replayTurn", "unknownTurn"),
+                rejection(&mut second_rx)
+            );
+        }
+
+        #[tokio::test]
+        async fn identity_across_games() {
+            let app = new_app();
+            let client_id = Uuid::new_v4();
+            let join_as = |game_id: &str| {
+                let (tx, rx) = mpsc::unbounded_channel();
+                let lifecycle = PlayerConnLifecycle {
+                    app: app.clone(),
+                    conn: PlayerConn::new(
+                        NEXT_CONN_ID.fetch_add(1, Ordering::Relaxed),
+                        tx,
+                        Encoding::Json,
+                    ),
+                    player_id: Uuid::new_v4(),
+                    player_nickname: None,
+                    new_player: true,
+                    game_id: game_id.to_string(),
+                    left: false,
+                    last_known_segment_order: None,
+                    client_id: Some(client_id),
+                    last_pong_at: None,
+                    binary_snapshot: false,
+                    max_message_bytes: usize::MAX,
+                };
+                (lifecycle, rx)
+            };
+            let nickname = |app: &AppState, game_id: &str, player_id: Uuid| {
+                let game = app.games.find(game_id).unwrap();
+                game.player(&player_id).unwrap().nickname.clone()
+            };
+
+            let (mut first, _first_rx) = join_as("first");
+            first.init().await;
+            let (mut second, _second_rx) = join_as("second");
+            second.init().await;
+
+            let app = app.lock().await;
+            assert_ne!(first.player_id, second.player_id);
+            assert_eq!(
+                nickname(&app, "first", first.player_id),
+                nickname(&app, "second", second.player_id)
+            );
+        }
+
+        #[tokio::test]
+        async fn unknown_event_type() {
+            let app = new_app();
+            let (mut first, mut first_rx) = join(&app, "test").await;
+            received(&mut first_rx);
+
+            first
+                .on_message(Message::text(
+                    r#"{"eventId":"1","body":{"type":"throwConfetti","amount":3}}"#,
+                ))
+                .await;
+            let messages = received(&mut first_rx);
+            assert_eq!(1, messages.len());
+            let event: serde_json::Value =
+                serde_json::from_str(messages[0].to_str().unwrap()).expect("Event");
+            assert_eq!("1", event["fromEventId"]);
+            assert_eq!("unsupportedEvent", event["body"]["type"]);
+            assert_eq!("throwConfetti", event["body"]["eventType"]);
+        }
+
+        #[tokio::test]
+        async fn mark_close() {
+            let app = new_app();
+            let (mut first, mut first_rx) = join(&app, "test").await;
+            let (mut second, mut second_rx) = join(&app, "test").await;
+            first
+                .on_message(Message::text(
+                    r#"{"body":{"type":"submitWord","word":"cat","canvas":{"width":500,"height":500}}}"#,
+                ))
+                .await;
+            received(&mut first_rx);
+            received(&mut second_rx);
+
+            let mark_close = |player_id: Uuid| {
+                Message::text(format!(
+                    r#"{{"body":{{"type":"markClose","playerId":"{}"}}}}"#,
+                    player_id
+                ))
+            };
+            second.on_message(mark_close(first.player_id)).await;
+            assert!(received(&mut first_rx).is_empty(), "only drawer can mark");
+            assert_eq!(vec!["actionRejected"], received_types(&mut second_rx));
+
+            first.on_message(mark_close(second.player_id)).await;
+            assert_eq!(vec!["markedClose"], received_types(&mut first_rx));
+            assert_eq!(vec!["markedClose"], received_types(&mut second_rx));
+        }
+    }
+}
+
+/// IncomingEvent represents every possible incoming message
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IncomingEvent {
     pub event_id: Option<String>,
     pub body: IncomingEventBody,
 }
@@ -570,6 +3274,10 @@ enum IncomingEventBody {
     RemoveDrawingSegment {
         segment_id: String,
     },
+    /// Drawing player has finished the segment
+    EndSegment {
+        id: String,
+    },
     SubmitWord {
         word: String,
         canvas: CanvasSize,
@@ -578,7 +3286,45 @@ enum IncomingEventBody {
         word: String,
     },
     AskWordTip {},
+    /// Guessing player gives up. Word is revealed once all guessing players give up.
+    GiveUp,
+    /// Host ends the round right away. Word is revealed and nobody gets points.
+    ForceNextRound,
+    /// Replay drawing of a past turn from game history
+    #[serde(rename_all = "camelCase")]
+    ReplayTurn {
+        turn_index: usize,
+    },
+    /// Drawing player hints that a guessing player is close to the answer
+    #[serde(rename_all = "camelCase")]
+    MarkClose {
+        player_id: Uuid,
+    },
+    /// Fetch the word with hidden letters, e.g. after reconnecting
+    GetWordMask,
+    /// Player steps away or comes back. Away players are not picked to draw.
+    SetAway {
+        away: bool,
+    },
+    /// Report an abusive player. Host is notified once enough players report the same player.
+    #[serde(rename_all = "camelCase")]
+    ReportPlayer {
+        player_id: Uuid,
+        reason: String,
+    },
+    /// Guessing player starts or stops typing a guess
+    Typing {
+        typing: bool,
+    },
+    /// Fetch players who joined or left since a known roster version
+    GetRosterSince {
+        version: u64,
+    },
+    Leave,
     Ping,
+    /// Event type this server doesn't know about, e.g. from a newer client
+    #[serde(other)]
+    Unknown,
 }
 
 /// OutgoingEvent represents every possible outgoing message
@@ -593,8 +3339,24 @@ struct OutgoingEvent {
 #[serde(tag = "type")]
 #[serde(rename_all = "camelCase")]
 enum OutgoingEventBody {
-    Game(Game),
+    /// First event on every connection
+    #[serde(rename_all = "camelCase")]
+    Hello {
+        server_version: &'static str,
+        features: Vec<&'static str>,
+    },
+    Game(Box<Game>),
     AddDrawingSegment(DrawingSegment),
+    /// All segments of the drawing. Replaces whatever drawing client has.
+    DrawingSnapshot {
+        segments: Vec<DrawingSegment>,
+    },
+    /// Segment points should be appended to an existing segment
+    #[serde(rename_all = "camelCase")]
+    MergeDrawingSegment {
+        into_segment_id: String,
+        segment: DrawingSegment,
+    },
     #[serde(rename_all = "camelCase")]
     RemoveDrawingSegment {
         segment_id: String,
@@ -603,17 +3365,596 @@ enum OutgoingEventBody {
     YouAre {
         player: Player,
     },
+    /// Action was dropped due to game rules. Action is the type of the incoming event.
+    ActionRejected {
+        action: &'static str,
+        reason: RejectReason,
+    },
+    /// Chosen word was rejected
+    InvalidWord {
+        reason: &'static str,
+    },
     WrongGuess {},
+    /// There is nothing to guess at the moment
+    NotGuessable {},
+    /// How many guessers are close to the word. Sent only to drawing player.
+    GuessProgress(GuessProgress),
+    /// Player cannot guess anymore until the next round
+    OutOfGuesses {},
+    /// Whether drawing player is drawing at the moment
+    DrawerActivity {
+        active: bool,
+    },
     WordTip {
         tip: String,
     },
+    /// Word with hidden letters. Never contains the full word.
+    WordMask {
+        mask: String,
+    },
     ClearDrawing {},
+    /// Segment of a replayed turn
+    #[serde(rename_all = "camelCase")]
+    ReplaySegment {
+        turn_index: usize,
+        segment: DrawingSegment,
+    },
+    /// All segments of a replayed turn were sent
+    #[serde(rename_all = "camelCase")]
+    ReplayDone {
+        turn_index: usize,
+    },
+    /// Drawing player marked a guessing player as close to the answer
+    #[serde(rename_all = "camelCase")]
+    MarkedClose {
+        player_id: Uuid,
+    },
+    /// Players who joined or left since the requested roster version
+    RosterDelta(RosterDelta),
+    /// Announcement from the operators, e.g. about upcoming maintenance
+    ServerNotice(ServerNotice),
+    /// Player was reported by several players. Sent only to the host.
+    #[serde(rename_all = "camelCase")]
+    PlayerReported {
+        player_id: Uuid,
+    },
+    /// Guessing player is typing a guess
+    #[serde(rename_all = "camelCase")]
+    PlayerTyping {
+        player_id: Uuid,
+        typing: bool,
+    },
+    /// Server doesn't support the received event type
+    #[serde(rename_all = "camelCase")]
+    UnsupportedEvent {
+        event_type: String,
+    },
+    /// Entry in the activity feed. Sent in addition to the events that changed the game.
+    #[serde(rename_all = "camelCase")]
+    Activity {
+        kind: ActivityKind,
+        player_id: Uuid,
+        text: String,
+    },
     Pong,
 }
 
+/// Game rules that can block an action
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+enum RejectReason {
+    /// Action is not possible at this stage of the game
+    WrongStage,
+    /// Another player is choosing or drawing
+    NotYourTurn,
+    /// Only the host can do that
+    NotHost,
+    /// Player is not allowed to do that at the moment
+    NotAllowed,
+    TooLong,
+    InvalidCanvas,
+    InvalidSegment,
+    Profanity,
+    UnknownTurn,
+    InvalidPlayer,
+    /// Another turn is still being replayed to the player
+    ReplayInProgress,
+}
+
+/// Kinds of entries in the game activity feed
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase")]
+enum ActivityKind {
+    Joined,
+    Left,
+    RoundStarted,
+    Guessed,
+}
+
+impl OutgoingEventBody {
+    /// Activity feed entry about the player
+    fn activity(kind: ActivityKind, player: &Player) -> Self {
+        let text = match kind {
+            ActivityKind::Joined => format!("{} joined", player.nickname),
+            ActivityKind::Left => format!("{} left", player.nickname),
+            ActivityKind::RoundStarted => format!("{} is drawing", player.nickname),
+            ActivityKind::Guessed => format!("{} guessed it!", player.nickname),
+        };
+        OutgoingEventBody::Activity {
+            kind,
+            player_id: player.id,
+            text,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct SyncQuery {
     pub game_id: String,
     pub player_id: Option<Uuid>,
     pub nickname: Option<String>,
+    /// Send only drawing changes newer than this order
+    pub last_known_segment_order: Option<u64>,
+    /// Stable identity of the browser. Keeps the nickname across games.
+    pub client_id: Option<Uuid>,
+    #[serde(default)]
+    pub encoding: Encoding,
+    /// Client can read binary drawing snapshots
+    #[serde(default)]
+    pub binary_snapshot: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GameExists {
+    exists: bool,
+    /// Whether new players can join the game
+    joinable: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatedGame {
+    pub game_id: String,
+    pub url: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RemovePlayerAfter {
+    pub seconds: u64,
+}
+
+/// Announcement from the operators to every connected player
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ServerNotice {
+    pub text: String,
+    #[serde(default)]
+    pub level: NoticeLevel,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NoticeLevel {
+    #[default]
+    Info,
+    Warning,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::games::GameSettings;
+
+    #[test]
+    fn players_to_remove_threshold() {
+        let mut app = AppState::new(Games::new(GamesConfig::default()));
+        let player_id = Uuid::new_v4();
+        let exited_at = Instant::now();
+        app.exited_players.insert(player_id, exited_at);
+        let now = exited_at + Duration::from_secs(90);
+
+        assert!(app.players_to_remove(now).is_empty(), "within default");
+
+        app.remove_player_after = Duration::from_secs(60);
+        assert_eq!(vec![player_id], app.players_to_remove(now), "lowered");
+    }
+
+    #[test]
+    fn close_orphaned_connections() {
+        let mut app = AppState::new(Games::new(GamesConfig::default()));
+        let player_id = Uuid::new_v4();
+        let orphan_id = Uuid::new_v4();
+        app.games.add_player("test", player_id, None);
+        let (tx, _rx) = mpsc::unbounded_channel();
+        app.connections
+            .insert(player_id, PlayerConn::new(0, tx, Encoding::Json));
+        let (tx, mut orphan_rx) = mpsc::unbounded_channel();
+        app.connections
+            .insert(orphan_id, PlayerConn::new(1, tx, Encoding::Json));
+
+        assert_eq!(vec![orphan_id], app.close_orphaned_connections());
+        assert!(app.connections.contains_key(&player_id), "player kept");
+        assert!(!app.connections.contains_key(&orphan_id), "orphan removed");
+        let msg = orphan_rx.try_recv().expect("Close").expect("Message");
+        assert!(msg.is_close());
+    }
+
+    #[test]
+    fn record_word_stats() {
+        let mut app = AppState::new(Games::new(GamesConfig {
+            end_round_without_guessers_after: Some(Duration::from_secs(0)),
+            ..GamesConfig::default()
+        }));
+        let player_1 = Uuid::new_v4();
+        let player_2 = Uuid::new_v4();
+        app.games.add_player("test", player_1, None);
+        app.games.add_player("test", player_2, None);
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+
+        // Guessed round
+        let game = app.games.find_mut("test").unwrap();
+        game.submit_word(&player_1, "Cat".to_string(), canvas.clone());
+        game.guess_word(&player_2, "cat");
+        let game = game.clone();
+        app.record_turn(&game);
+
+        // Round ended without a guess
+        let game = app.games.find_mut("test").unwrap();
+        game.submit_word(&player_2, "cat".to_string(), canvas);
+        assert_eq!(
+            Some(true),
+            game.update_guessers_presence(false, Instant::now())
+        );
+        let game = game.clone();
+        app.record_turn(&game);
+
+        let stats = &app.word_stats["cat"];
+        assert_eq!(2, stats.rounds, "rounds");
+        assert_eq!(1, stats.guessed, "guessed");
+        assert_eq!(0.5, stats.success_rate, "success rate");
+    }
+
+    #[tokio::test]
+    async fn create_game_negotiates_response() {
+        let app = Arc::new(Mutex::new(AppState::new(
+            Games::new(GamesConfig::default()),
+        )));
+        let filter = filters::create_game(app.clone(), false);
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/")
+            .header("accept", "application/json")
+            .reply(&filter)
+            .await;
+        assert_eq!(201, res.status(), "json status");
+        let body: serde_json::Value = serde_json::from_slice(res.body()).expect("Json body");
+        let game_id = body["gameId"].as_str().expect("Game id");
+        assert_eq!(format!("/game/{}", game_id), body["url"], "url");
+        assert!(app.lock().await.games.exists(game_id), "game reserved");
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/")
+            .header("accept", "text/html")
+            .reply(&filter)
+            .await;
+        assert_eq!(301, res.status(), "redirect status");
+        let location = res.headers()["location"].to_str().expect("Location");
+        assert!(location.starts_with("/game/"), "redirect location");
+    }
+
+    #[tokio::test]
+    async fn create_game_rejects_invalid_settings() {
+        let app = Arc::new(Mutex::new(AppState::new(
+            Games::new(GamesConfig::default()),
+        )));
+        let filter = filters::create_game(app.clone(), false);
+
+        for query in &["scoring=bogus", "maxPlayers=abc", "allowedTools=brush"] {
+            let res = warp::test::request()
+                .method("POST")
+                .path(&format!("/?{}", query))
+                .header("accept", "application/json")
+                .reply(&filter)
+                .await;
+            assert_eq!(400, res.status(), "{}", query);
+        }
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/?maxPlayers=4")
+            .header("accept", "application/json")
+            .reply(&filter)
+            .await;
+        assert_eq!(201, res.status(), "valid settings");
+    }
+
+    #[tokio::test]
+    async fn create_game_with_palette() {
+        let app = Arc::new(Mutex::new(AppState::new(
+            Games::new(GamesConfig::default()),
+        )));
+        let filter = filters::create_game(app.clone(), false);
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/?palette=rgb(255,%200,%200),%23000000")
+            .header("accept", "application/json")
+            .reply(&filter)
+            .await;
+        assert_eq!(201, res.status());
+        let body: serde_json::Value = serde_json::from_slice(res.body()).expect("Json body");
+        let game_id = body["gameId"].as_str().expect("Game id");
+
+        let mut app = app.lock().await;
+        let player_id = Uuid::new_v4();
+        app.games.add_player(game_id, player_id, None);
+        let game = app.games.find_mut(game_id).expect("Game");
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+        game.submit_word(&player_id, "cat".to_string(), canvas);
+        let mut segment: DrawingSegment = serde_json::from_value(serde_json::json!({
+            "id": "a",
+            "stroke": "rgb(240, 20, 10)",
+            "lineWidth": 2,
+            "points": [{"x": 0, "y": 0}],
+        }))
+        .expect("Segment");
+        assert!(game.add_segment(&player_id, &mut segment).is_some());
+        let segment = serde_json::to_value(&segment).unwrap();
+        assert_eq!("rgb(255, 0, 0)", segment["stroke"], "snapped to palette");
+    }
+
+    #[tokio::test]
+    async fn create_game_with_allowed_tools() {
+        let app = Arc::new(Mutex::new(AppState::new(
+            Games::new(GamesConfig::default()),
+        )));
+        let filter = filters::create_game(app.clone(), false);
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/?allowedTools=pen")
+            .header("accept", "application/json")
+            .reply(&filter)
+            .await;
+        assert_eq!(201, res.status());
+        let body: serde_json::Value = serde_json::from_slice(res.body()).expect("Json body");
+        let game_id = body["gameId"].as_str().expect("Game id");
+
+        let mut app = app.lock().await;
+        let player_id = Uuid::new_v4();
+        app.games.add_player(game_id, player_id, None);
+        let game = app.games.find_mut(game_id).expect("Game");
+        let canvas = CanvasSize {
+            width: 100,
+            height: 100,
+        };
+        game.submit_word(&player_id, "star".to_string(), canvas);
+        let parse = |json| serde_json::from_str::<DrawingSegment>(json).expect("Segment");
+        let stamp = r#"{"id":"s1","stamp":"⭐","at":{"x":10,"y":20},"size":32}"#;
+        assert!(
+            game.add_segment(&player_id, &mut parse(stamp)).is_none(),
+            "stamp"
+        );
+        let line = r#"{"id":"l1","stroke":"red","lineWidth":2,"points":[{"x":0,"y":0}]}"#;
+        assert!(
+            game.add_segment(&player_id, &mut parse(line)).is_some(),
+            "pen"
+        );
+    }
+
+    #[tokio::test]
+    async fn create_game_rate_limited() {
+        let new_filter = |exempt_localhost: bool, trust_proxy: bool| {
+            let mut app_state = AppState::new(Games::new(GamesConfig::default()));
+            app_state.max_rooms_per_minute = Some(2);
+            app_state.exempt_localhost = exempt_localhost;
+            filters::create_game(Arc::new(Mutex::new(app_state)), trust_proxy)
+        };
+        let create = |filter, addr: &str, forwarded: Option<&str>| {
+            let mut req = warp::test::request()
+                .method("POST")
+                .path("/")
+                .header("accept", "application/json")
+                .remote_addr(addr.parse().expect("Address"));
+            if let Some(forwarded) = forwarded {
+                req = req.header("x-forwarded-for", forwarded);
+            }
+            async move { req.reply(&filter).await.status() }
+        };
+
+        let filter = new_filter(false, false);
+        assert_eq!(201, create(filter.clone(), "10.0.0.1:5000", None).await);
+        assert_eq!(201, create(filter.clone(), "10.0.0.1:5001", None).await);
+        assert_eq!(
+            429,
+            create(filter.clone(), "10.0.0.1:5002", None).await,
+            "throttled"
+        );
+        assert_eq!(
+            201,
+            create(filter.clone(), "10.0.0.2:5000", None).await,
+            "other address"
+        );
+        // Forwarded addresses are ignored without a trusted proxy
+        for forwarded in &["127.0.0.1", "1.2.3.4", "5.6.7.8"] {
+            assert_eq!(
+                429,
+                create(filter.clone(), "10.0.0.1:5003", Some(forwarded)).await,
+                "spoofed {}",
+                forwarded
+            );
+        }
+        for _ in 0..2 {
+            create(filter.clone(), "127.0.0.1:5000", None).await;
+        }
+        assert_eq!(
+            429,
+            create(filter.clone(), "127.0.0.1:5000", None).await,
+            "localhost outside of development"
+        );
+
+        let filter = new_filter(true, false);
+        for _ in 0..3 {
+            assert_eq!(
+                201,
+                create(filter.clone(), "127.0.0.1:5000", None).await,
+                "localhost in development"
+            );
+        }
+
+        // Behind a trusted proxy the address added by the proxy counts
+        let filter = new_filter(false, true);
+        for _ in 0..2 {
+            create(filter.clone(), "10.0.0.9:5000", Some("1.2.3.4, 10.0.0.3")).await;
+        }
+        assert_eq!(
+            429,
+            create(filter.clone(), "10.0.0.9:5000", Some("5.6.7.8, 10.0.0.3")).await,
+            "same proxy added address"
+        );
+        assert_eq!(
+            201,
+            create(filter.clone(), "10.0.0.9:5000", Some("10.0.0.4")).await,
+            "other proxy added address"
+        );
+    }
+
+    #[tokio::test]
+    async fn game_exists() {
+        let app = Arc::new(Mutex::new(AppState::new(
+            Games::new(GamesConfig::default()),
+        )));
+        let (pending_id, full_id) = {
+            let mut app = app.lock().await;
+            let pending_id = app.games.reserve_id(GameSettings::default());
+            let full_id = app.games.reserve_id(GameSettings {
+                max_players: Some(1),
+                ..GameSettings::default()
+            });
+            app.games.add_player(&full_id, Uuid::new_v4(), None);
+            (pending_id, full_id)
+        };
+        let filter = filters::game_exists(app.clone());
+
+        let exists = |game_id: String| {
+            let filter = filter.clone();
+            async move {
+                let res = warp::test::request()
+                    .path(&format!("/game/{}/exists", game_id))
+                    .reply(&filter)
+                    .await;
+                assert_eq!(200, res.status());
+                let body: serde_json::Value =
+                    serde_json::from_slice(res.body()).expect("Json body");
+                (body["exists"].clone(), body["joinable"].clone())
+            }
+        };
+        assert_eq!(
+            (true.into(), true.into()),
+            exists(pending_id).await,
+            "pending"
+        );
+        assert_eq!(
+            (false.into(), false.into()),
+            exists("unknown".to_string()).await,
+            "unknown"
+        );
+        assert_eq!((true.into(), false.into()), exists(full_id).await, "full");
+    }
+
+    #[test]
+    fn connection_slots_cap() {
+        let slots = ConnectionSlots::new(Some(2));
+        let first = slots.try_acquire().expect("First slot");
+        let second = slots.try_acquire().expect("Second slot");
+        assert_eq!(2, slots.active());
+        assert!(slots.try_acquire().is_none(), "at capacity");
+
+        drop(first);
+        assert_eq!(1, slots.active());
+        let third = slots.try_acquire();
+        assert!(third.is_some(), "slot released");
+        drop(second);
+        drop(third);
+        assert_eq!(0, slots.active());
+
+        let unlimited = ConnectionSlots::new(None);
+        let held: Vec<_> = (0..100).filter_map(|_| unlimited.try_acquire()).collect();
+        assert_eq!(100, held.len());
+    }
+
+    #[tokio::test]
+    async fn set_remove_player_after_requires_token() {
+        let app = Arc::new(Mutex::new(AppState::new(
+            Games::new(GamesConfig::default()),
+        )));
+        let filter = filters::set_remove_player_after(app.clone(), Some("secret".to_string()));
+
+        let res = warp::test::request()
+            .method("PUT")
+            .path("/admin/remove-player-after")
+            .header("authorization", "Bearer wrong")
+            .json(&RemovePlayerAfter { seconds: 60 })
+            .reply(&filter)
+            .await;
+        assert_eq!(404, res.status(), "wrong token");
+
+        let res = warp::test::request()
+            .method("PUT")
+            .path("/admin/remove-player-after")
+            .header("authorization", "Bearer secret")
+            .json(&RemovePlayerAfter { seconds: 60 })
+            .reply(&filter)
+            .await;
+        assert_eq!(200, res.status(), "valid token");
+        assert_eq!(
+            Duration::from_secs(60),
+            app.lock().await.remove_player_after
+        );
+    }
+    #[tokio::test]
+    async fn server_notice() {
+        let app = Arc::new(Mutex::new(AppState::new(
+            Games::new(GamesConfig::default()),
+        )));
+        let mut receivers = vec![];
+        {
+            let mut app = app.lock().await;
+            for conn_id in 0..3 {
+                let (tx, rx) = mpsc::unbounded_channel();
+                app.connections
+                    .insert(Uuid::new_v4(), PlayerConn::new(conn_id, tx, Encoding::Json));
+                receivers.push(rx);
+            }
+        }
+        let filter = filters::server_notice(app.clone(), Some("secret".to_string()));
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/admin/notice")
+            .header("authorization", "Bearer secret")
+            .json(&ServerNotice {
+                text: "Restarting in 5 minutes".to_string(),
+                level: NoticeLevel::Warning,
+            })
+            .reply(&filter)
+            .await;
+        assert_eq!(200, res.status());
+
+        for rx in &mut receivers {
+            let msg = rx.try_recv().expect("Notice").expect("Message");
+            let event: serde_json::Value =
+                serde_json::from_str(msg.to_str().unwrap()).expect("Event");
+            assert_eq!("serverNotice", event["body"]["type"]);
+            assert_eq!("Restarting in 5 minutes", event["body"]["text"]);
+            assert_eq!("warning", event["body"]["level"]);
+        }
+    }
 }
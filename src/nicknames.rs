@@ -0,0 +1,219 @@
+use rand::{seq::SliceRandom, Rng};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+use unicode_segmentation::UnicodeSegmentation;
+
+const ADJECTIVES: &[&str] = &[
+    "Blue", "Brave", "Calm", "Clever", "Curious", "Fast", "Funny", "Gentle", "Golden", "Happy",
+    "Lucky", "Mighty", "Quiet", "Red", "Shiny", "Silly", "Sleepy", "Sunny", "Swift", "Wild",
+];
+
+const NOUNS: &[&str] = &[
+    "Bear",
+    "Beaver",
+    "Crocodile",
+    "Dolphin",
+    "Eagle",
+    "Fox",
+    "Hedgehog",
+    "Koala",
+    "Lion",
+    "Moose",
+    "Otter",
+    "Owl",
+    "Panda",
+    "Penguin",
+    "Rabbit",
+    "Seal",
+    "Tiger",
+    "Turtle",
+    "Whale",
+    "Wolf",
+];
+
+/// Max length of a nickname in user perceived characters
+const MAX_GRAPHEMES: usize = 20;
+
+/// Max chars in a single user perceived character, e.g. emoji joined by U+200D
+const MAX_CHARS_PER_GRAPHEME: usize = 10;
+
+/// Max combining marks stacked on a single user perceived character
+const MAX_MARKS_PER_GRAPHEME: usize = 2;
+
+/// How many times to try picking a unique word pair before falling back to a numbered name
+const MAX_ATTEMPTS: usize = 20;
+
+/// Generate a friendly nickname from adjective and noun pair (e.g. "BlueFox")
+/// that is not among the taken ones.
+pub fn generate(rng: &mut impl Rng, taken: &[&str]) -> String {
+    let mut pick = || {
+        format!(
+            "{}{}",
+            ADJECTIVES.choose(rng).expect("Adjective"),
+            NOUNS.choose(rng).expect("Noun")
+        )
+    };
+
+    for _ in 0..MAX_ATTEMPTS {
+        let name = pick();
+        if !taken.contains(&name.as_str()) {
+            return name;
+        }
+    }
+
+    // Too many collisions, make the name unique with a number
+    let name = pick();
+    (2..)
+        .map(|n| format!("{}{}", name, n))
+        .find(|numbered| !taken.contains(&numbered.as_str()))
+        .expect("Unique name")
+}
+
+/// Clean up a nickname chosen by a player: normalize to NFC, strip invisible and control
+/// characters and limit the length. Return None if nothing is left.
+pub fn sanitize(nickname: &str) -> Option<String> {
+    let chars: Vec<char> = nickname
+        .chars()
+        .take(MAX_GRAPHEMES * MAX_CHARS_PER_GRAPHEME)
+        .nfc()
+        .filter(|c| !c.is_control())
+        .collect();
+    let cleaned: String = chars
+        .iter()
+        .enumerate()
+        .filter(|(i, c)| !is_zero_width(**c) || is_emoji_joiner(&chars, *i))
+        .map(|(_, c)| *c)
+        .collect();
+    let name: String = cleaned
+        .trim()
+        .graphemes(true)
+        .take(MAX_GRAPHEMES)
+        .map(limit_grapheme)
+        .collect();
+    let name = name.trim_end();
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+fn is_zero_width(c: char) -> bool {
+    matches!(c, '\u{200B}'..='\u{200D}' | '\u{2060}' | '\u{FEFF}')
+}
+
+/// Whether the char at index is a zero width joiner between two emoji, e.g. in a family emoji
+fn is_emoji_joiner(chars: &[char], i: usize) -> bool {
+    chars[i] == '\u{200D}'
+        && i > 0
+        && is_emoji(chars[i - 1])
+        && chars.get(i + 1).is_some_and(|c| is_emoji(*c))
+}
+
+/// Rough emoji check: pictographic blocks and the emoji presentation selector
+fn is_emoji(c: char) -> bool {
+    matches!(
+        c,
+        '\u{FE0F}' | '\u{2300}'..='\u{27BF}' | '\u{2B00}'..='\u{2BFF}' | '\u{1F000}'..='\u{1FAFF}'
+    )
+}
+
+/// Drop extra combining marks and chars of a user perceived character
+fn limit_grapheme(grapheme: &str) -> String {
+    let mut marks = 0;
+    let limited: String = grapheme
+        .chars()
+        .filter(|c| {
+            if !is_combining_mark(*c) {
+                return true;
+            }
+            marks += 1;
+            marks <= MAX_MARKS_PER_GRAPHEME
+        })
+        .take(MAX_CHARS_PER_GRAPHEME)
+        .collect();
+    limited.trim_end_matches('\u{200D}').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    fn is_word_pair(name: &str) -> bool {
+        ADJECTIVES.iter().any(|adj| {
+            name.starts_with(adj) && NOUNS.iter().any(|noun| &name[adj.len()..] == *noun)
+        })
+    }
+
+    #[test]
+    fn generate_unique_names() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut taken: Vec<String> = vec![];
+
+        for _ in 0..50 {
+            let names: Vec<&str> = taken.iter().map(|n| n.as_str()).collect();
+            let name = generate(&mut rng, &names);
+            assert!(is_word_pair(&name), "{} is made of word lists", name);
+            assert!(!taken.contains(&name), "{} is unique", name);
+            taken.push(name);
+        }
+    }
+
+    #[test]
+    fn sanitize_strips_invisible_characters() {
+        assert_eq!(
+            Some("Alice".to_string()),
+            sanitize("\u{200B}Al\u{200D}ice\u{FEFF}\n ")
+        );
+        assert_eq!(None, sanitize("\u{200B}\u{2060} "), "nothing left");
+        // "e" followed by a combining acute accent is composed into a single char
+        assert_eq!(Some("\u{E9}".to_string()), sanitize("e\u{301}"));
+    }
+
+    #[test]
+    fn sanitize_keeps_emoji_sequences() {
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        assert_eq!(
+            Some(format!("Bob{}", family)),
+            sanitize(&format!("Bob{}", family))
+        );
+        let rainbow_flag = "\u{1F3F3}\u{FE0F}\u{200D}\u{1F308}";
+        assert_eq!(Some(rainbow_flag.to_string()), sanitize(rainbow_flag));
+        assert_eq!(
+            Some("A\u{1F600}".to_string()),
+            sanitize("A\u{200D}\u{1F600}"),
+            "joiner after a letter"
+        );
+    }
+
+    #[test]
+    fn sanitize_limits_combining_marks() {
+        let zalgo = format!("a{}b", "\u{301}".repeat(500));
+        let name = sanitize(&zalgo).expect("Name");
+        assert_eq!(1, name.graphemes(true).count(), "rest is cut off");
+        assert_eq!(
+            format!("\u{E1}{}", "\u{301}".repeat(MAX_MARKS_PER_GRAPHEME)),
+            name
+        );
+
+        let long = "\u{1F600}".repeat(10_000);
+        let name = sanitize(&long).expect("Name");
+        assert_eq!(MAX_GRAPHEMES, name.chars().count());
+    }
+
+    #[test]
+    fn sanitize_counts_graphemes() {
+        let flag = "\u{1F1EB}\u{1F1EE}";
+        let name = sanitize(&flag.repeat(MAX_GRAPHEMES + 5)).expect("Name");
+        assert_eq!(MAX_GRAPHEMES, name.graphemes(true).count());
+        assert_eq!(flag.repeat(MAX_GRAPHEMES), name);
+    }
+
+    #[test]
+    fn generate_is_deterministic() {
+        let first = generate(&mut StdRng::seed_from_u64(7), &[]);
+        let second = generate(&mut StdRng::seed_from_u64(7), &[]);
+        assert_eq!(first, second);
+    }
+}
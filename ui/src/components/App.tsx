@@ -177,6 +177,17 @@ class App extends Component<Props, State> {
 
   renderOverlay() {
     const game = db.game();
+    if (game?.stage.type === 'waitingForPlayers') {
+      return (
+        <div class={styles.overlay}>
+          <div class={styles.overlayContent}>
+            <ShareGame />
+            <label>Waiting for other players to join.</label>
+          </div>
+        </div>
+      );
+    }
+
     if (game === null || game.stage.type !== 'playerChoosing') {
       return null;
     }
@@ -6,6 +6,7 @@ import ReconnectingWS from './util/ws';
 
 enum StorageKey {
   Player = 'player',
+  ClientId = 'clientId',
 }
 
 // Known message types
@@ -13,6 +14,7 @@ enum MessageType {
   Ping = 'ping',
   Pong = 'pong',
   AddDrawingSegment = 'addDrawingSegment',
+  DrawingSnapshot = 'drawingSnapshot',
   RemoveDrawingSegment = 'removeDrawingSegment',
   SubmitWord = 'submitWord',
   GuessWord = 'guessWord',
@@ -55,6 +57,11 @@ interface AddDrawingSegment extends DrawingSegment {
   type: MessageType.AddDrawingSegment;
 }
 
+interface DrawingSnapshot {
+  type: MessageType.DrawingSnapshot;
+  segments: DrawingSegment[];
+}
+
 interface DeleteDrawingSegment {
   type: MessageType.RemoveDrawingSegment;
   segmentId: string;
@@ -82,7 +89,7 @@ interface AskWordTip {
 
 interface Incoming {
   fromEventId: string;
-  body: Pong | Game | AddDrawingSegment | DeleteDrawingSegment | YouAre | WrongGuess | ClearDrawing | WordTip;
+  body: Pong | Game | AddDrawingSegment | DrawingSnapshot | DeleteDrawingSegment | YouAre | WrongGuess | ClearDrawing | WordTip;
 }
 
 interface Ping {
@@ -114,7 +121,7 @@ interface ClearDrawing {
 interface Game {
   type: MessageType.Game;
   id: string;
-  stage: PlayerChoosing | PlayerDrawing;
+  stage: PlayerChoosing | PlayerDrawing | RoundSummary | WaitingForPlayers;
   players: Player[];
   history: Turn[];
 }
@@ -132,9 +139,22 @@ interface PlayerDrawing {
   };
 }
 
+interface RoundSummary {
+  type: 'roundSummary',
+  playerId: number;
+  word: string;
+  guessedBy?: number;
+}
+
+interface WaitingForPlayers {
+  type: 'waitingForPlayers',
+  playerId: number;
+}
+
 interface Player {
   id: number;
   nickname: number;
+  away: boolean;
 }
 
 interface Turn {
@@ -274,7 +294,7 @@ export default {
   connect: function connect(gameId: string, { onError }: ConnectOptions) {
     const host = location.host;
     const protocol = location.protocol === 'https:' ? 'wss' : 'ws';
-    let url = `${protocol}://${host}/sync?game_id=${gameId}`;
+    let url = `${protocol}://${host}/sync?game_id=${gameId}&client_id=${clientId()}`;
     if (db.player) {
       url += `&player_id=${db.player.id}&nickname=${encodeURIComponent(db.player.nickname)}`;
     }
@@ -316,6 +336,12 @@ export default {
             nextEvolution();
             break;
 
+          case MessageType.DrawingSnapshot:
+            db.drawing.clear();
+            event.body.segments.forEach((segment) => db.drawing.set(segment.id, segment));
+            nextEvolution();
+            break;
+
           case MessageType.RemoveDrawingSegment:
             db.drawing.delete(event.body.segmentId);
             nextEvolution();
@@ -473,6 +499,18 @@ function readSavedPlayer(): Player | null {
   }
 }
 
+/**
+ * Stable identity of this browser across games
+ */
+function clientId(): string {
+  let id = localStorage.getItem(StorageKey.ClientId);
+  if (!id) {
+    id = crypto.randomUUID();
+    localStorage.setItem(StorageKey.ClientId, id);
+  }
+  return id;
+}
+
 /**
  * Save player in local storage
  */
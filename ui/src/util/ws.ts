@@ -3,6 +3,10 @@
  */
 
 const MAX_TRIES = 5;
+// Close codes sent by the server when reconnecting makes no sense
+const CLOSE_LEFT = 1000;
+const CLOSE_REPLACED = 4000;
+const CLOSE_FULL = 4001;
 
 class ReconnectingWS {
   private ws: WebSocket;
@@ -42,7 +46,13 @@ class ReconnectingWS {
       this.onerror(e);
     };
 
-    ws.onclose = () => {
+    ws.onclose = (e) => {
+      if (e.code === CLOSE_LEFT || e.code === CLOSE_REPLACED || e.code === CLOSE_FULL) {
+        // Server closed the connection on purpose
+        this.onclose();
+        return;
+      }
+
       this.onreconnect(this.tries);
 
       if (this.tries >= MAX_TRIES) {